@@ -0,0 +1,878 @@
+/* Copyright 2019 Matthew Traudt */
+/* MIT License */
+//! Storage for the children of each [`Trie`](../struct.Trie.html) node.
+//!
+//! A trie is generic over a [`Children`](trait.Children.html) backend, which picks the map type
+//! holding each node's children. [`HashChildren`](struct.HashChildren.html) is the default;
+//! [`BTreeChildren`](struct.BTreeChildren.html) keeps siblings sorted so that iteration yields
+//! keys in lexicographic order. For tries with low fan-out, such as byte-keyed ones,
+//! [`SortedVecChildren`](struct.SortedVecChildren.html) and
+//! [`InlineChildren`](struct.InlineChildren.html) avoid the per-node overhead of a tree or hash
+//! table while also keeping siblings sorted.
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::{btree_map, hash_map, BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::ops::{Bound, Range, RangeBounds};
+use std::{array, mem, slice, vec};
+
+/// A map from a single key element to a child node.
+///
+/// Implemented for the map types used by the [`Children`](trait.Children.html) backends.
+pub trait ChildMap<K, T>: Sized {
+    /// Iterator over `(key, child)` pairs, in the map's order.
+    type Iter<'a>: Iterator<Item = (&'a K, &'a T)>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    /// Iterator over `(key, child)` pairs with mutable access to the children, in the map's
+    /// order.
+    type IterMut<'a>: Iterator<Item = (&'a K, &'a mut T)>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    /// Owning iterator over `(key, child)` pairs, in the map's order.
+    type IntoIter: Iterator<Item = (K, T)>;
+
+    /// Create an empty map configured like this one, for the children of a new node.
+    fn new_empty(&self) -> Self;
+    /// Return the number of children.
+    fn len(&self) -> usize;
+    /// Return whether there are no children.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Return the child stored under `key`, if any.
+    fn get(&self, key: &K) -> Option<&T>;
+    /// Return the child stored under `key` mutably, if any.
+    fn get_mut(&mut self, key: &K) -> Option<&mut T>;
+    /// Return whether a child is stored under `key`.
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Store `child` under `key`, returning the child previously stored there.
+    fn insert(&mut self, key: K, child: T) -> Option<T>;
+    /// Return the child stored under `key`, first storing the result of `default` if there is
+    /// none.
+    fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, default: F) -> &mut T;
+    /// Remove and return the child stored under `key`, if any.
+    fn remove(&mut self, key: &K) -> Option<T>;
+    /// Iterate over `(key, child)` pairs.
+    fn iter(&self) -> Self::Iter<'_>;
+    /// Iterate over `(key, child)` pairs, with mutable access to the children.
+    fn iter_mut(&mut self) -> Self::IterMut<'_>;
+    /// Consume the map, iterating over `(key, child)` pairs.
+    fn into_iter(self) -> Self::IntoIter;
+}
+
+/// A backend choosing the [`ChildMap`](trait.ChildMap.html) type used by every node of a trie.
+pub trait Children<K> {
+    /// The map from key element to child node.
+    type Map<T>: ChildMap<K, T>;
+}
+
+/// A [`Children`](trait.Children.html) backend that can find a child by any borrowed form `Q`
+/// of the key element, as `HashMap::get` can.
+pub trait LookupChildren<K, Q: ?Sized>: Children<K> {
+    /// Return the stored key and the child for `key`, if any.
+    fn get<'a, T>(map: &'a Self::Map<T>, key: &Q) -> Option<(&'a K, &'a T)>;
+    /// Return the child for `key` mutably, if any.
+    fn get_mut<'a, T>(map: &'a mut Self::Map<T>, key: &Q) -> Option<&'a mut T>;
+}
+
+/// An element of a key sequence passed to a lookup such as
+/// [`Trie::get`](../struct.Trie.html#method.get), giving the borrowed form `Key` that is
+/// compared against the stored key elements.
+///
+/// References look up by what they point to, so `&[K]` and iterators of `&str` work as keys.
+/// Owned primitives and `String`s look up by themselves, so iterators such as `str::chars` or
+/// `BufRead::lines` can be passed without collecting them first.
+pub trait LookupKey {
+    /// The borrowed form of the key element.
+    type Key: ?Sized;
+    /// Borrow the key element for lookup.
+    fn lookup_key(&self) -> &Self::Key;
+}
+
+impl<Q: ?Sized> LookupKey for &Q {
+    type Key = Q;
+
+    fn lookup_key(&self) -> &Q {
+        self
+    }
+}
+
+impl<Q: ?Sized> LookupKey for &mut Q {
+    type Key = Q;
+
+    fn lookup_key(&self) -> &Q {
+        self
+    }
+}
+
+macro_rules! owned_lookup_key {
+    ($($t:ty),*) => {
+        $(
+            impl LookupKey for $t {
+                type Key = $t;
+
+                fn lookup_key(&self) -> &$t {
+                    self
+                }
+            }
+        )*
+    };
+}
+
+owned_lookup_key!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, String
+);
+
+/// A [`Children`](trait.Children.html) backend that keeps siblings sorted by key, so that the
+/// children within a range of keys can be visited from either end.
+pub trait OrderedChildren<K>: Children<K> {
+    /// Iterate in key order over the children whose keys fall within `range`.
+    ///
+    /// As with `BTreeMap::range`, the bounds may be any borrowed form `Q` of the key element,
+    /// and this may panic if the range's start is after its end.
+    fn range<'a, T, Q, R>(
+        map: &'a Self::Map<T>,
+        range: R,
+    ) -> <Self::Map<T> as ChildMap<K, T>>::Iter<'a>
+    where
+        K: Borrow<Q> + 'a,
+        T: 'a,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>;
+    /// Take the last remaining `(key, child)` pair from an iterator returned by `range` or
+    /// [`ChildMap::iter`](trait.ChildMap.html#tymethod.iter), as
+    /// `DoubleEndedIterator::next_back` does.
+    fn next_back<'a, T>(
+        iter: &mut <Self::Map<T> as ChildMap<K, T>>::Iter<'a>,
+    ) -> Option<(&'a K, &'a T)>
+    where
+        K: 'a,
+        T: 'a;
+}
+
+/// Store children in a `HashMap` whose hashers are built by `S`. Siblings are kept in an
+/// arbitrary order.
+///
+/// Every child map is given a clone of its parent's `S`, so a trie created with
+/// [`Trie::with_hasher`](../struct.Trie.html#method.with_hasher) uses that hasher throughout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashChildren<S = RandomState>(PhantomData<S>);
+
+/// Store children in a `BTreeMap`. Siblings are kept sorted, so a trie iterates over its keys
+/// in lexicographic order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BTreeChildren;
+
+/// Store children in a [`SortedVecMap`](struct.SortedVecMap.html). Siblings are kept sorted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SortedVecChildren;
+
+/// Store up to `N` children inline in each node, in an [`InlineMap`](struct.InlineMap.html).
+/// Siblings are kept sorted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InlineChildren<const N: usize = 4>;
+
+/// A map kept as a `Vec` of `(key, value)` pairs sorted by key, searched with binary search.
+///
+/// A node costs a single allocation for all of its children, which suits small fan-outs.
+#[derive(Debug)]
+pub struct SortedVecMap<K, T> {
+    entries: Vec<(K, T)>,
+}
+
+impl<K, T> SortedVecMap<K, T> {
+    fn find<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.binary_search_by(|(k, _)| k.borrow().cmp(key))
+    }
+}
+
+impl<K, T> Default for SortedVecMap<K, T> {
+    fn default() -> Self {
+        SortedVecMap { entries: vec![] }
+    }
+}
+
+// The indices of the entries, sorted by `key`, whose keys fall within `range`
+fn indices_within<E, K, R>(entries: &[E], key: impl Fn(&E) -> &K, range: &R) -> Range<usize>
+where
+    K: Ord + ?Sized,
+    R: RangeBounds<K>,
+{
+    let start = match range.start_bound() {
+        Bound::Included(s) => entries.partition_point(|e| key(e) < s),
+        Bound::Excluded(s) => entries.partition_point(|e| key(e) <= s),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => entries.partition_point(|e| key(e) <= end),
+        Bound::Excluded(end) => entries.partition_point(|e| key(e) < end),
+        Bound::Unbounded => entries.len(),
+    };
+    start..end.max(start)
+}
+
+/// Iterator over the entries of a [`SortedVecMap`](struct.SortedVecMap.html), in key order.
+#[derive(Debug)]
+pub struct SortedVecIter<'a, K, T> {
+    iter: slice::Iter<'a, (K, T)>,
+}
+
+impl<'a, K, T> Iterator for SortedVecIter<'a, K, T> {
+    type Item = (&'a K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, t)| (k, t))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, T> DoubleEndedIterator for SortedVecIter<'a, K, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(k, t)| (k, t))
+    }
+}
+
+/// Mutable iterator over the entries of a [`SortedVecMap`](struct.SortedVecMap.html), in key
+/// order.
+#[derive(Debug)]
+pub struct SortedVecIterMut<'a, K, T> {
+    iter: slice::IterMut<'a, (K, T)>,
+}
+
+impl<'a, K, T> Iterator for SortedVecIterMut<'a, K, T> {
+    type Item = (&'a K, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Keys stay shared so that the order can't be broken
+        self.iter.next().map(|(k, t)| (&*k, t))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// A map that keeps up to `N` entries, sorted by key, inline and moves them to a `Vec` once it
+/// grows beyond that.
+///
+/// Values are boxed so that the map can be stored inline in its own values, as trie nodes are.
+#[derive(Debug)]
+pub struct InlineMap<K, T, const N: usize> {
+    slots: Slots<K, T, N>,
+}
+
+// Entries are packed at the front and sorted by key. The heap variant holds no empty slots; it
+// uses the same slot type only so that both variants can be handled as one slice.
+#[derive(Debug)]
+enum Slots<K, T, const N: usize> {
+    Inline(usize, [Option<(K, Box<T>)>; N]),
+    Heap(Vec<Option<(K, Box<T>)>>),
+}
+
+// Every slot handed out by InlineMap::filled holds an entry
+fn filled_entry<K, T>(slot: &Option<(K, Box<T>)>) -> (&K, &T) {
+    let (k, t) = slot.as_ref().unwrap();
+    (k, t)
+}
+
+impl<K, T, const N: usize> InlineMap<K, T, N> {
+    fn filled(&self) -> &[Option<(K, Box<T>)>] {
+        match &self.slots {
+            Slots::Inline(len, slots) => &slots[..*len],
+            Slots::Heap(slots) => slots,
+        }
+    }
+
+    fn filled_mut(&mut self) -> &mut [Option<(K, Box<T>)>] {
+        match &mut self.slots {
+            Slots::Inline(len, slots) => &mut slots[..*len],
+            Slots::Heap(slots) => slots,
+        }
+    }
+
+    fn child_mut(&mut self, i: usize) -> &mut T {
+        &mut self.filled_mut()[i].as_mut().unwrap().1
+    }
+
+    fn find<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.filled()
+            .binary_search_by(|slot| filled_entry(slot).0.borrow().cmp(key))
+    }
+
+    fn insert_at(&mut self, i: usize, key: K, child: T) {
+        let entry = Some((key, Box::new(child)));
+        match &mut self.slots {
+            Slots::Inline(len, slots) if *len < N => {
+                // Shift the first empty slot down to i
+                slots[i..=*len].rotate_right(1);
+                slots[i] = entry;
+                *len += 1;
+            }
+            Slots::Inline(_, slots) => {
+                let mut heap = slots.iter_mut().map(Option::take).collect::<Vec<_>>();
+                heap.insert(i, entry);
+                self.slots = Slots::Heap(heap);
+            }
+            Slots::Heap(slots) => slots.insert(i, entry),
+        }
+    }
+
+    fn remove_at(&mut self, i: usize) -> T {
+        let entry = match &mut self.slots {
+            Slots::Inline(len, slots) => {
+                let entry = slots[i].take();
+                // Shift the now empty slot up past the remaining entries
+                slots[i..*len].rotate_left(1);
+                *len -= 1;
+                entry
+            }
+            Slots::Heap(slots) => slots.remove(i),
+        };
+        *entry.unwrap().1
+    }
+}
+
+impl<K, T, const N: usize> Default for InlineMap<K, T, N> {
+    fn default() -> Self {
+        InlineMap {
+            slots: Slots::Inline(0, array::from_fn(|_| None)),
+        }
+    }
+}
+
+/// Iterator over the entries of an [`InlineMap`](struct.InlineMap.html), in key order.
+#[derive(Debug)]
+pub struct InlineIter<'a, K, T> {
+    iter: slice::Iter<'a, Option<(K, Box<T>)>>,
+}
+
+impl<'a, K, T> Iterator for InlineIter<'a, K, T> {
+    type Item = (&'a K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(filled_entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, T> DoubleEndedIterator for InlineIter<'a, K, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(filled_entry)
+    }
+}
+
+/// Mutable iterator over the entries of an [`InlineMap`](struct.InlineMap.html), in key order.
+#[derive(Debug)]
+pub struct InlineIterMut<'a, K, T> {
+    iter: slice::IterMut<'a, Option<(K, Box<T>)>>,
+}
+
+impl<'a, K, T> Iterator for InlineIterMut<'a, K, T> {
+    type Item = (&'a K, &'a mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|slot| {
+            let (k, t) = slot.as_mut().unwrap();
+            (&*k, &mut **t)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Owning iterator over the entries of an [`InlineMap`](struct.InlineMap.html), in key order.
+#[derive(Debug)]
+pub struct InlineIntoIter<K, T, const N: usize> {
+    slots: SlotsIntoIter<K, T, N>,
+}
+
+#[derive(Debug)]
+enum SlotsIntoIter<K, T, const N: usize> {
+    Inline(array::IntoIter<Option<(K, Box<T>)>, N>),
+    Heap(vec::IntoIter<Option<(K, Box<T>)>>),
+}
+
+impl<K, T, const N: usize> Iterator for InlineIntoIter<K, T, N> {
+    type Item = (K, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot = match &mut self.slots {
+            SlotsIntoIter::Inline(iter) => iter.next(),
+            SlotsIntoIter::Heap(iter) => iter.next(),
+        };
+        // The first empty slot marks the end of the entries
+        slot.flatten().map(|(k, t)| (k, *t))
+    }
+}
+
+impl<K, T, S> ChildMap<K, T> for HashMap<K, T, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    type Iter<'a>
+        = hash_map::Iter<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IterMut<'a>
+        = hash_map::IterMut<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IntoIter = hash_map::IntoIter<K, T>;
+
+    fn new_empty(&self) -> Self {
+        HashMap::with_hasher(self.hasher().clone())
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn get(&self, key: &K) -> Option<&T> {
+        HashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut T> {
+        HashMap::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, child: T) -> Option<T> {
+        HashMap::insert(self, key, child)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, default: F) -> &mut T {
+        self.entry(key).or_insert_with(default)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<T> {
+        HashMap::remove(self, key)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        HashMap::iter(self)
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        HashMap::iter_mut(self)
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self)
+    }
+}
+
+impl<K, T> ChildMap<K, T> for BTreeMap<K, T>
+where
+    K: Ord,
+{
+    // A Range rather than an Iter, so that OrderedChildren::range can return the same type
+    type Iter<'a>
+        = btree_map::Range<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IterMut<'a>
+        = btree_map::IterMut<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IntoIter = btree_map::IntoIter<K, T>;
+
+    fn new_empty(&self) -> Self {
+        BTreeMap::new()
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn get(&self, key: &K) -> Option<&T> {
+        BTreeMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut T> {
+        BTreeMap::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, child: T) -> Option<T> {
+        BTreeMap::insert(self, key, child)
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, default: F) -> &mut T {
+        self.entry(key).or_insert_with(default)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<T> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        BTreeMap::range(self, ..)
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        BTreeMap::iter_mut(self)
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter(self)
+    }
+}
+
+impl<K, T> ChildMap<K, T> for SortedVecMap<K, T>
+where
+    K: Ord,
+{
+    type Iter<'a>
+        = SortedVecIter<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IterMut<'a>
+        = SortedVecIterMut<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IntoIter = vec::IntoIter<(K, T)>;
+
+    fn new_empty(&self) -> Self {
+        SortedVecMap::default()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get(&self, key: &K) -> Option<&T> {
+        let i = self.find(key).ok()?;
+        Some(&self.entries[i].1)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut T> {
+        let i = self.find(key).ok()?;
+        Some(&mut self.entries[i].1)
+    }
+
+    fn insert(&mut self, key: K, child: T) -> Option<T> {
+        match self.find(&key) {
+            Ok(i) => Some(mem::replace(&mut self.entries[i].1, child)),
+            Err(i) => {
+                self.entries.insert(i, (key, child));
+                None
+            }
+        }
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, default: F) -> &mut T {
+        let i = match self.find(&key) {
+            Ok(i) => i,
+            Err(i) => {
+                self.entries.insert(i, (key, default()));
+                i
+            }
+        };
+        &mut self.entries[i].1
+    }
+
+    fn remove(&mut self, key: &K) -> Option<T> {
+        let i = self.find(key).ok()?;
+        Some(self.entries.remove(i).1)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        SortedVecIter {
+            iter: self.entries.iter(),
+        }
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        SortedVecIterMut {
+            iter: self.entries.iter_mut(),
+        }
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<K, T, const N: usize> ChildMap<K, T> for InlineMap<K, T, N>
+where
+    K: Ord,
+{
+    type Iter<'a>
+        = InlineIter<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IterMut<'a>
+        = InlineIterMut<'a, K, T>
+    where
+        Self: 'a,
+        K: 'a,
+        T: 'a;
+    type IntoIter = InlineIntoIter<K, T, N>;
+
+    fn new_empty(&self) -> Self {
+        InlineMap::default()
+    }
+
+    fn len(&self) -> usize {
+        self.filled().len()
+    }
+
+    fn get(&self, key: &K) -> Option<&T> {
+        let i = self.find(key).ok()?;
+        Some(filled_entry(&self.filled()[i]).1)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut T> {
+        let i = self.find(key).ok()?;
+        Some(self.child_mut(i))
+    }
+
+    fn insert(&mut self, key: K, child: T) -> Option<T> {
+        match self.find(&key) {
+            Ok(i) => Some(mem::replace(self.child_mut(i), child)),
+            Err(i) => {
+                self.insert_at(i, key, child);
+                None
+            }
+        }
+    }
+
+    fn get_or_insert_with<F: FnOnce() -> T>(&mut self, key: K, default: F) -> &mut T {
+        let i = match self.find(&key) {
+            Ok(i) => i,
+            Err(i) => {
+                self.insert_at(i, key, default());
+                i
+            }
+        };
+        self.child_mut(i)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<T> {
+        let i = self.find(key).ok()?;
+        Some(self.remove_at(i))
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        InlineIter {
+            iter: self.filled().iter(),
+        }
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        InlineIterMut {
+            iter: self.filled_mut().iter_mut(),
+        }
+    }
+
+    fn into_iter(self) -> Self::IntoIter {
+        let slots = match self.slots {
+            Slots::Inline(_, slots) => SlotsIntoIter::Inline(IntoIterator::into_iter(slots)),
+            Slots::Heap(slots) => SlotsIntoIter::Heap(slots.into_iter()),
+        };
+        InlineIntoIter { slots }
+    }
+}
+
+impl<K, S> Children<K> for HashChildren<S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    type Map<T> = HashMap<K, T, S>;
+}
+
+impl<K> Children<K> for BTreeChildren
+where
+    K: Ord,
+{
+    type Map<T> = BTreeMap<K, T>;
+}
+
+impl<K> Children<K> for SortedVecChildren
+where
+    K: Ord,
+{
+    type Map<T> = SortedVecMap<K, T>;
+}
+
+impl<K, const N: usize> Children<K> for InlineChildren<N>
+where
+    K: Ord,
+{
+    type Map<T> = InlineMap<K, T, N>;
+}
+
+impl<K, Q, S> LookupChildren<K, Q> for HashChildren<S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ?Sized,
+    S: BuildHasher + Clone,
+{
+    fn get<'a, T>(map: &'a HashMap<K, T, S>, key: &Q) -> Option<(&'a K, &'a T)> {
+        map.get_key_value(key)
+    }
+
+    fn get_mut<'a, T>(map: &'a mut HashMap<K, T, S>, key: &Q) -> Option<&'a mut T> {
+        map.get_mut(key)
+    }
+}
+
+impl<K, Q> LookupChildren<K, Q> for BTreeChildren
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn get<'a, T>(map: &'a BTreeMap<K, T>, key: &Q) -> Option<(&'a K, &'a T)> {
+        map.get_key_value(key)
+    }
+
+    fn get_mut<'a, T>(map: &'a mut BTreeMap<K, T>, key: &Q) -> Option<&'a mut T> {
+        map.get_mut(key)
+    }
+}
+
+impl<K, Q> LookupChildren<K, Q> for SortedVecChildren
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn get<'a, T>(map: &'a SortedVecMap<K, T>, key: &Q) -> Option<(&'a K, &'a T)> {
+        let (k, t) = &map.entries[map.find(key).ok()?];
+        Some((k, t))
+    }
+
+    fn get_mut<'a, T>(map: &'a mut SortedVecMap<K, T>, key: &Q) -> Option<&'a mut T> {
+        let i = map.find(key).ok()?;
+        Some(&mut map.entries[i].1)
+    }
+}
+
+impl<K, Q, const N: usize> LookupChildren<K, Q> for InlineChildren<N>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn get<'a, T>(map: &'a InlineMap<K, T, N>, key: &Q) -> Option<(&'a K, &'a T)> {
+        let i = map.find(key).ok()?;
+        Some(filled_entry(&map.filled()[i]))
+    }
+
+    fn get_mut<'a, T>(map: &'a mut InlineMap<K, T, N>, key: &Q) -> Option<&'a mut T> {
+        let i = map.find(key).ok()?;
+        Some(map.child_mut(i))
+    }
+}
+
+impl<K> OrderedChildren<K> for BTreeChildren
+where
+    K: Ord,
+{
+    fn range<'a, T, Q, R>(map: &'a BTreeMap<K, T>, range: R) -> btree_map::Range<'a, K, T>
+    where
+        K: Borrow<Q> + 'a,
+        T: 'a,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        map.range(range)
+    }
+
+    fn next_back<'a, T>(iter: &mut btree_map::Range<'a, K, T>) -> Option<(&'a K, &'a T)>
+    where
+        K: 'a,
+        T: 'a,
+    {
+        iter.next_back()
+    }
+}
+
+impl<K> OrderedChildren<K> for SortedVecChildren
+where
+    K: Ord,
+{
+    fn range<'a, T, Q, R>(map: &'a SortedVecMap<K, T>, range: R) -> SortedVecIter<'a, K, T>
+    where
+        K: Borrow<Q> + 'a,
+        T: 'a,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let within = indices_within(&map.entries, |(k, _)| k.borrow(), &range);
+        SortedVecIter {
+            iter: map.entries[within].iter(),
+        }
+    }
+
+    fn next_back<'a, T>(iter: &mut SortedVecIter<'a, K, T>) -> Option<(&'a K, &'a T)>
+    where
+        K: 'a,
+        T: 'a,
+    {
+        iter.next_back()
+    }
+}
+
+impl<K, const N: usize> OrderedChildren<K> for InlineChildren<N>
+where
+    K: Ord,
+{
+    fn range<'a, T, Q, R>(map: &'a InlineMap<K, T, N>, range: R) -> InlineIter<'a, K, T>
+    where
+        K: Borrow<Q> + 'a,
+        T: 'a,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let filled = map.filled();
+        let within = indices_within(filled, |slot| filled_entry(slot).0.borrow(), &range);
+        InlineIter {
+            iter: filled[within].iter(),
+        }
+    }
+
+    fn next_back<'a, T>(iter: &mut InlineIter<'a, K, T>) -> Option<(&'a K, &'a T)>
+    where
+        K: 'a,
+        T: 'a,
+    {
+        iter.next_back()
+    }
+}
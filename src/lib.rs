@@ -1,225 +1,2066 @@
 /* Copyright 2019 Matthew Traudt */
 /* MIT License */
+//! A generic trie (prefix tree) mapping sequences of keys to values.
+//!
+//! Each element of a key sequence selects a child node, so `[1, 2, 3]` and `[1, 2, 4]` share the
+//! nodes for `1` and `2`. Any node, not just leaves, may hold a value.
+//!
+//! ```
+//! use trie::Trie;
+//!
+//! let mut t: Trie<char, u32> = Trie::new(None);
+//! t.insert("ab".chars(), 1);
+//! t.insert(&['a', 'b', 'c'], 2);
+//! assert_eq!(t.fetch("ab".chars()), Some(1));
+//! assert_eq!(t.get("abc".chars()), Some(&2));
+//! assert_eq!(t.fetch(&['a']), None);
+//! assert_eq!(t.keys().count(), 2);
+//! ```
+use std::borrow::Borrow;
 use std::clone::Clone;
 use std::cmp::Eq;
 use std::collections::HashMap;
-use std::fmt::Debug;
-use std::hash::Hash;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
+use std::slice;
 
-#[macro_use]
 extern crate serde;
 extern crate serde_cbor;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Trie<K, V>
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+pub mod children;
+
+pub use crate::children::{
+    BTreeChildren, ChildMap, Children, HashChildren, InlineChildren, LookupChildren, LookupKey,
+    OrderedChildren, SortedVecChildren,
+};
+
+/// A trie mapping sequences of `K` to values of type `V`.
+///
+/// Every node of the trie is itself a `Trie`: it holds an optional value and a map from the next
+/// key element to the child node.
+///
+/// The children of each node are stored in the map chosen by the
+/// [`Children`](trait.Children.html) backend `C`. With the default
+/// [`HashChildren`](struct.HashChildren.html), siblings are visited in an arbitrary order; with
+/// [`BTreeChildren`](struct.BTreeChildren.html) (see [`OrderedTrie`](type.OrderedTrie.html))
+/// they are kept sorted, so iteration yields key sequences in lexicographic order.
+///
+/// Every operation walks the trie with a loop rather than recursion, including dropping and
+/// (de)serializing it, so key sequences may be arbitrarily long.
+pub struct Trie<K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    val: Option<V>,
+    children: C::Map<Trie<K, V, C>>,
+    // Number of values stored in this node and all of its descendants
+    len: usize,
+}
+
+// Where Trie::unlink left the node that values are being removed from
+enum Unlinked<'a, 'k, K, V, C, B>
+where
+    C: Children<K>,
+{
+    // Still in the trie, at the end of the key sequence
+    Attached(&'a mut Trie<K, V, C>),
+    // Below the root of a subtrie cut off from the trie, at the given keys from its root
+    Detached(Trie<K, V, C>, &'k [B]),
+}
+
+/// A [`Trie`](struct.Trie.html) whose iterators yield key sequences in lexicographic order.
+pub type OrderedTrie<K, V> = Trie<K, V, BTreeChildren>;
+
+impl<K, V, C> Trie<K, V, C>
+where
+    C: Children<K>,
+    C::Map<Trie<K, V, C>>: Default,
+{
+    /// Create a new trie whose root (the empty key sequence) holds `val`.
+    pub fn new(val: Option<V>) -> Self {
+        Self {
+            len: val.is_some() as usize,
+            val,
+            children: Default::default(),
+        }
+    }
+}
+
+impl<K, V, S> Trie<K, V, HashChildren<S>>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    /// Create a new trie whose root holds `val` and whose child maps all use hashers built by
+    /// `hash_builder`, as with `HashMap::with_hasher`.
+    ///
+    /// ```
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// use trie::{HashChildren, Trie};
+    ///
+    /// // DefaultHasher::new() always uses the same keys, so the layout is reproducible
+    /// type Fixed = BuildHasherDefault<DefaultHasher>;
+    /// let mut t: Trie<u8, u32, HashChildren<Fixed>> = Trie::with_hasher(None, Fixed::default());
+    /// t.insert(b"key", 1);
+    /// assert_eq!(t.get(b"key"), Some(&1));
+    /// ```
+    pub fn with_hasher(val: Option<V>, hash_builder: S) -> Self {
+        Self {
+            len: val.is_some() as usize,
+            val,
+            children: HashMap::with_hasher(hash_builder),
+        }
+    }
+
+    /// Return a reference to the trie's `BuildHasher`.
+    pub fn hasher(&self) -> &S {
+        self.children.hasher()
+    }
+}
+
+impl<K, V, C> Trie<K, V, C>
+where
+    C: Children<K>,
+{
+    /// Return the number of values stored in the trie.
+    ///
+    /// Every node keeps count of the values below it, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return whether the trie has no values stored in it.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the key sequences that have a value stored at them.
+    ///
+    /// A key is always yielded before any longer key that it is a prefix of. Siblings are visited
+    /// in the order of the child map, so keys come out in lexicographic order with
+    /// [`BTreeChildren`](struct.BTreeChildren.html) and in an arbitrary order with
+    /// [`HashChildren`](struct.HashChildren.html).
+    pub fn keys<'a>(&'a self) -> TrieKeyIter<'a, K, V, C> {
+        TrieKeyIter { iter: self.iter() }
+    }
+
+    /// Iterate over the stored values, in the same order as [`keys`](#method.keys).
+    pub fn values<'a>(&'a self) -> TrieValueIter<'a, K, V, C> {
+        TrieValueIter { iter: self.iter() }
+    }
+
+    /// Iterate over `(keys, value)` pairs, in the same order as [`keys`](#method.keys).
+    ///
+    /// With a backend that keeps siblings sorted, such as
+    /// [`BTreeChildren`](struct.BTreeChildren.html), the iterator is double-ended, so
+    /// `iter().rev()` walks the trie backwards in reverse lexicographic order:
+    ///
+    /// ```
+    /// use trie::OrderedTrie;
+    ///
+    /// let mut t: OrderedTrie<u32, &str> = OrderedTrie::new(None);
+    /// t.insert(&[2019, 12], "december");
+    /// t.insert(&[2020, 1], "january");
+    /// t.insert(&[2020, 2], "february");
+    /// let newest = t.iter().rev().map(|(_, v)| *v).collect::<Vec<_>>();
+    /// assert_eq!(newest, ["february", "january", "december"]);
+    /// ```
+    pub fn iter<'a>(&'a self) -> TrieIter<'a, K, V, C> {
+        self.iter_from(vec![])
+    }
+
+    /// Return the first `(keys, value)` pair in the order of [`keys`](#method.keys), which is the
+    /// lexicographically smallest key sequence, or `None` if the trie is empty.
+    ///
+    /// Only available with a backend that keeps siblings sorted, such as
+    /// [`BTreeChildren`](struct.BTreeChildren.html).
+    pub fn first(&self) -> Option<(Vec<&K>, &V)>
+    where
+        C: OrderedChildren<K>,
+    {
+        self.iter().next()
+    }
+
+    /// Return the last `(keys, value)` pair in the order of [`keys`](#method.keys), which is the
+    /// lexicographically largest key sequence, or `None` if the trie is empty.
+    ///
+    /// Only available with a backend that keeps siblings sorted, like [`first`](#method.first).
+    /// Only the path to the last entry is walked, rather than the whole trie.
+    pub fn last(&self) -> Option<(Vec<&K>, &V)>
+    where
+        C: OrderedChildren<K>,
+    {
+        self.iter().next_back()
+    }
+
+    /// Stream over `(keys, value)` pairs, in the same order as [`keys`](#method.keys).
+    ///
+    /// Unlike [`iter`](#method.iter), which hands out a new `Vec` of keys for every entry, the
+    /// stream lends out a single key buffer that it reuses, so walking the trie doesn't allocate
+    /// per entry. The price is that each key slice must be dropped before asking for the next
+    /// entry, so the stream can't implement `Iterator`.
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<char, u32> = Trie::new(None);
+    /// t.insert("ab".chars(), 1);
+    /// t.insert("abc".chars(), 2);
+    /// let mut stream = t.stream();
+    /// let mut total = 0;
+    /// while let Some((keys, val)) = stream.next() {
+    ///     total += keys.len() as u32 * val;
+    /// }
+    /// assert_eq!(total, 8);
+    /// ```
+    pub fn stream<'a>(&'a self) -> TrieStream<'a, K, V, C> {
+        self.stream_from(vec![])
+    }
+
+    /// Iterate over `(keys, value)` pairs with mutable access to the values, in the same order as
+    /// [`keys`](#method.keys).
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<char, u32> = Trie::new(None);
+    /// t.insert("ab".chars(), 10);
+    /// t.insert("ac".chars(), 20);
+    /// for (_, score) in t.iter_mut() {
+    ///     *score /= 2;
+    /// }
+    /// assert_eq!(t.get(&['a', 'c']), Some(&10));
+    /// ```
+    pub fn iter_mut<'a>(&'a mut self) -> TrieIterMut<'a, K, V, C> {
+        TrieIterMut {
+            root: Some(self),
+            stack: vec![],
+            keys: vec![],
+        }
+    }
+
+    /// Iterate over the stored values mutably, in the same order as [`keys`](#method.keys).
+    pub fn values_mut<'a>(&'a mut self) -> TrieValueIterMut<'a, K, V, C> {
+        TrieValueIterMut {
+            iter: self.iter_mut(),
+        }
+    }
+
+    /// Iterate over `(keys, value)` pairs whose key sequence starts with `prefix`, in the same
+    /// order as [`keys`](#method.keys).
+    ///
+    /// The yielded key sequences include the prefix. Key elements of `prefix` may be any borrowed
+    /// form of `K`, as with [`get`](#method.get).
+    ///
+    /// ```
+    /// use trie::OrderedTrie;
+    ///
+    /// let mut t: OrderedTrie<&str, u32> = OrderedTrie::new(None);
+    /// t.insert(&["src", "lib.rs"], 1);
+    /// t.insert(&["src", "children.rs"], 2);
+    /// t.insert(&["Cargo.toml"], 3);
+    /// let listing = t.iter_prefix(&["src"]).collect::<Vec<_>>();
+    /// assert_eq!(listing, [(vec![&"src", &"children.rs"], &2), (vec![&"src", &"lib.rs"], &1)]);
+    /// ```
+    pub fn iter_prefix<'a, Q, I>(&'a self, prefix: I) -> TrieIter<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        let mut node = self;
+        let mut keys_above = Vec::new();
+        for k in prefix {
+            match C::get(&node.children, k.lookup_key()) {
+                Some((key, child)) => {
+                    keys_above.push(key);
+                    node = child;
+                }
+                None => return TrieIter::empty(),
+            }
+        }
+        node.iter_from(keys_above)
+    }
+
+    /// Iterate over the key sequences that start with `prefix`, in the same order as
+    /// [`iter_prefix`](#method.iter_prefix).
+    pub fn keys_with_prefix<'a, Q, I>(&'a self, prefix: I) -> TrieKeyIter<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        TrieKeyIter {
+            iter: self.iter_prefix(prefix),
+        }
+    }
+
+    /// Iterate over the values stored under `prefix`, in the same order as
+    /// [`iter_prefix`](#method.iter_prefix).
+    pub fn values_with_prefix<'a, Q, I>(&'a self, prefix: I) -> TrieValueIter<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        TrieValueIter {
+            iter: self.iter_prefix(prefix),
+        }
+    }
+
+    // Iterate over this node and its descendants, whose key sequences all start with `keys_above`
+    fn iter_from<'a>(&'a self, keys_above: Vec<&'a K>) -> TrieIter<'a, K, V, C> {
+        TrieIter {
+            back: TrieBackWalk {
+                root: Some(self),
+                stack: vec![],
+                nodes: vec![],
+                keys: keys_above.clone(),
+            },
+            front: self.stream_from(keys_above),
+            remaining: self.len,
+        }
+    }
+
+    // Stream over this node and its descendants, whose key sequences all start with `keys_above`
+    fn stream_from<'a>(&'a self, keys_above: Vec<&'a K>) -> TrieStream<'a, K, V, C> {
+        TrieStream {
+            root: Some(self),
+            stack: vec![],
+            keys: keys_above,
+        }
+    }
+
+    // Walk the nodes below this one as serialized records
+    fn records<'a>(&'a self) -> TrieRecords<'a, K, V, C> {
+        TrieRecords {
+            root: Some(self),
+            stack: vec![],
+            pops: 0,
+        }
+    }
+}
+
+impl<K, V, C> Trie<K, V, C>
+where
+    C: Children<K>,
+{
+    /// Return a reference to the value stored at `keys`, if any.
+    ///
+    /// As with `HashMap::get`, the key elements may be any borrowed form of `K`, so a
+    /// `Trie<String, V>` can be queried with `&str` segments without allocating. `keys` yields
+    /// either `&Q` or, for primitive and `String` keys, owned elements; see
+    /// [`LookupKey`](children/trait.LookupKey.html). Segments already held in a slice such as
+    /// `&[&str]`, which yields `&&str`, are passed with `.iter().copied()`:
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<String, u32> = Trie::new(None);
+    /// t.insert(&["usr".to_string(), "bin".to_string()], 1);
+    /// assert_eq!(t.get("usr/bin".split('/')), Some(&1));
+    /// assert_eq!(t.get(&["usr".to_string()]), None);
+    /// let segs: &[&str] = &["usr", "bin"];
+    /// assert_eq!(t.get(segs.iter().copied()), Some(&1));
+    /// ```
+    pub fn get<Q, I>(&self, keys: I) -> Option<&V>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        self.node(keys)?.val.as_ref()
+    }
+
+    /// Return a mutable reference to the value stored at `keys`, if any.
+    ///
+    /// Key elements may be any borrowed form of `K`, as with [`get`](#method.get).
+    pub fn get_mut<Q, I>(&mut self, keys: I) -> Option<&mut V>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        self.node_mut(keys)?.val.as_mut()
+    }
+
+    /// Return whether a value is stored at `keys`.
+    ///
+    /// Key elements may be any borrowed form of `K`, as with [`get`](#method.get).
+    pub fn contains_key<Q, I>(&self, keys: I) -> bool
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        self.get(keys).is_some()
+    }
+
+    /// Return the node reached by following `prefix` as a trie of its own, if it exists.
+    ///
+    /// Keys in the returned trie are relative to `prefix`. Key elements may be any borrowed form
+    /// of `K`, as with [`get`](#method.get).
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<char, u32> = Trie::new(None);
+    /// t.insert("tea".chars(), 1);
+    /// t.insert("ten".chars(), 2);
+    /// let te = t.subtrie(&['t', 'e']).unwrap();
+    /// assert_eq!(te.get(&['a']), Some(&1));
+    /// assert_eq!(te.values().count(), 2);
+    /// ```
+    pub fn subtrie<Q, I>(&self, prefix: I) -> Option<&Trie<K, V, C>>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        self.node(prefix)
+    }
+
+    /// Return the node reached by following `prefix` as a mutable trie of its own, if it exists.
+    ///
+    /// Keys in the returned trie are relative to `prefix`, as with [`subtrie`](#method.subtrie).
+    /// The returned [`SubtrieMut`](struct.SubtrieMut.html) dereferences to the subtrie, and
+    /// puts it back in place, bringing the value counts of the nodes above it up to date, when it
+    /// is dropped. The node at `prefix` is kept even if it is left with neither a value nor
+    /// children; use [`remove_prefix`](#method.remove_prefix) to drop a whole subtrie.
+    pub fn subtrie_mut<'a, Q, I>(&'a mut self, prefix: I) -> Option<SubtrieMut<'a, K, V, C>>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        let mut node = self;
+        let mut counts = vec![];
+        for k in prefix {
+            let Trie { children, len, .. } = node;
+            counts.push(len);
+            node = C::get_mut(children, k.lookup_key())?;
+        }
+        let empty = node.empty_like();
+        let sub = mem::replace(node, empty);
+        for len in counts.iter_mut() {
+            **len -= sub.len;
+        }
+        Some(SubtrieMut {
+            counts,
+            slot: node,
+            sub,
+        })
+    }
+
+    /// Find the deepest node along `keys` that has a value stored at it, and return how many key
+    /// elements lead to it along with its value.
+    ///
+    /// The root counts as a match of length 0. Key elements may be any borrowed form of `K`, as
+    /// with [`get`](#method.get).
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<&str, u32> = Trie::new(None);
+    /// t.insert(&["api"], 1);
+    /// t.insert(&["api", "v1", "users"], 2);
+    /// assert_eq!(t.longest_prefix_match(&["api", "v1", "groups"]), Some((1, &1)));
+    /// assert_eq!(t.longest_prefix_match(&["api", "v1", "users", "7"]), Some((3, &2)));
+    /// assert_eq!(t.longest_prefix_match(&["static"]), None);
+    /// ```
+    pub fn longest_prefix_match<Q, I>(&self, keys: I) -> Option<(usize, &V)>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        self.prefixes_of(keys).last()
+    }
+
+    /// Iterate over the nodes along `keys` that have a value stored at them, shortest first,
+    /// yielding how many key elements lead to each node along with its value.
+    ///
+    /// The root is yielded first, with length 0, if it has a value. Key elements may be any
+    /// borrowed form of `K`, as with [`get`](#method.get).
+    ///
+    /// ```
+    /// use trie::Trie;
+    ///
+    /// let mut t: Trie<&str, &str> = Trie::new(Some("root"));
+    /// t.insert(&["src"], "src");
+    /// t.insert(&["src", "lib.rs"], "lib");
+    /// let layers = t.prefixes_of(&["src", "lib.rs"]).collect::<Vec<_>>();
+    /// assert_eq!(layers, [(0, &"root"), (1, &"src"), (2, &"lib")]);
+    /// ```
+    pub fn prefixes_of<'a, Q, I>(&'a self, keys: I) -> TriePrefixes<'a, K, V, I::IntoIter, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        TriePrefixes {
+            node: Some(self),
+            keys: keys.into_iter(),
+            depth: 0,
+        }
+    }
+
+    /// Remove the value stored at `keys` and return it, if any.
+    ///
+    /// Nodes along `keys` that are left without any values at or below them are dropped from the
+    /// trie.
+    pub fn remove<I>(&mut self, keys: I) -> Option<V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        // Make sure there is a value to remove before touching any counts
+        self.descend(&keys)?.val.as_ref()?;
+        match self.unlink(&keys, 1) {
+            Unlinked::Attached(node) => {
+                node.len -= 1;
+                node.val.take()
+            }
+            Unlinked::Detached(mut sub, rest) => sub.descend_mut(rest).val.take(),
+        }
+    }
+
+    /// Detach the node reached by following `keys` and return it as a trie of its own, if it
+    /// exists.
+    ///
+    /// Keys in the returned trie are relative to `keys`. Nodes along `keys` that are left without
+    /// any values at or below them are dropped, as with [`remove`](#method.remove). Removing the
+    /// empty prefix takes the whole trie, leaving `self` empty.
+    pub fn remove_prefix<I>(&mut self, keys: I) -> Option<Trie<K, V, C>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let count = self.descend(&keys)?.len;
+        let node = match self.unlink(&keys, count) {
+            Unlinked::Attached(node) => node,
+            Unlinked::Detached(sub, []) => return Some(sub),
+            Unlinked::Detached(mut sub, rest) => {
+                let node = sub.descend_mut(rest);
+                let empty = node.empty_like();
+                return Some(mem::replace(node, empty));
+            }
+        };
+        let empty = node.empty_like();
+        Some(mem::replace(node, empty))
+    }
+
+    // Take `count` values off the counts of the nodes above the one at `keys`, which must exist
+    // and hold at least `count` values at or below it. The first node along the way that would be
+    // left without any values is detached and returned, along with the keys leading from it to
+    // the target node, so that removing the values leaves no empty nodes behind. If there is no
+    // such node, the target node is returned in place.
+    fn unlink<'k, B>(&mut self, keys: &'k [B], count: usize) -> Unlinked<'_, 'k, K, V, C, B>
+    where
+        B: Borrow<K>,
+    {
+        let mut node = self;
+        for (i, k) in keys.iter().enumerate() {
+            node.len -= count;
+            if node.children.get(k.borrow()).unwrap().len == count {
+                let sub = node.children.remove(k.borrow()).unwrap();
+                return Unlinked::Detached(sub, &keys[i + 1..]);
+            }
+            node = node.children.get_mut(k.borrow()).unwrap();
+        }
+        Unlinked::Attached(node)
+    }
+
+    fn descend<B>(&self, keys: &[B]) -> Option<&Trie<K, V, C>>
+    where
+        B: Borrow<K>,
+    {
+        let mut node = self;
+        for k in keys {
+            node = node.children.get(k.borrow())?;
+        }
+        Some(node)
+    }
+
+    // Follow `keys`, which must lead to an existing node
+    fn descend_mut<B>(&mut self, keys: &[B]) -> &mut Trie<K, V, C>
+    where
+        B: Borrow<K>,
+    {
+        let mut node = self;
+        for k in keys {
+            node = node.children.get_mut(k.borrow()).unwrap();
+        }
+        node
+    }
+
+    // Take the node's children, leaving it without any so that it can be dropped cheaply
+    fn take_children(&mut self) -> C::Map<Trie<K, V, C>> {
+        let empty = self.children.new_empty();
+        self.len = self.val.is_some() as usize;
+        mem::replace(&mut self.children, empty)
+    }
+
+    // Create an empty node whose child map is configured like this node's
+    // The value stored at this node as a slice of zero or one elements
+    fn val_slice(&self) -> &[V] {
+        match &self.val {
+            Some(v) => slice::from_ref(v),
+            None => &[],
+        }
+    }
+
+    fn empty_like(&self) -> Self {
+        Trie {
+            val: None,
+            children: self.children.new_empty(),
+            len: 0,
+        }
+    }
+
+    fn node<Q, I>(&self, keys: I) -> Option<&Trie<K, V, C>>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        let mut node = self;
+        for k in keys {
+            node = C::get(&node.children, k.lookup_key())?.1;
+        }
+        Some(node)
+    }
+
+    fn node_mut<Q, I>(&mut self, keys: I) -> Option<&mut Trie<K, V, C>>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        Q: ?Sized,
+        C: LookupChildren<K, Q>,
+    {
+        let mut node = self;
+        for k in keys {
+            node = C::get_mut(&mut node.children, k.lookup_key())?;
+        }
+        Some(node)
+    }
+}
+
+impl<K, V, C> Trie<K, V, C>
+where
+    K: Ord,
+    C: OrderedChildren<K>,
+{
+    /// Iterate over the `(keys, value)` pairs whose key sequences fall within `range`, in
+    /// lexicographic order.
+    ///
+    /// As with `BTreeMap::range`, the bounds may be unbounded, inclusive or exclusive, and the
+    /// iterator is double-ended. A key sequence comes after all of its prefixes. A range whose
+    /// start is after its end is empty.
+    ///
+    /// ```
+    /// use trie::OrderedTrie;
+    ///
+    /// let mut t: OrderedTrie<u32, &str> = OrderedTrie::new(None);
+    /// t.insert(&[2020, 1, 31], "a");
+    /// t.insert(&[2020, 2], "b");
+    /// t.insert(&[2020, 2, 1], "c");
+    /// t.insert(&[2020, 3, 1], "d");
+    /// let start: &[u32] = &[2020, 2];
+    /// let end: &[u32] = &[2020, 3];
+    /// let feb = t.range(start..end).map(|(_, v)| *v).collect::<Vec<_>>();
+    /// assert_eq!(feb, ["b", "c"]);
+    /// assert_eq!(t.range(..=start).count(), 2);
+    /// ```
+    pub fn range<'a, 'b, R>(&'a self, range: R) -> TrieIter<'a, K, V, C>
+    where
+        R: RangeBounds<&'b [K]>,
+        K: 'b,
+    {
+        let (start, end) = (range.start_bound().cloned(), range.end_bound().cloned());
+        let before_start = match start {
+            Bound::Included(keys) => self.rank(keys, false),
+            Bound::Excluded(keys) => self.rank(keys, true),
+            Bound::Unbounded => 0,
+        };
+        let up_to_end = match end {
+            Bound::Included(keys) => self.rank(keys, true),
+            Bound::Excluded(keys) => self.rank(keys, false),
+            Bound::Unbounded => self.len,
+        };
+        let front = match start {
+            Bound::Included(keys) => self.seek_front(keys, true),
+            Bound::Excluded(keys) => self.seek_front(keys, false),
+            Bound::Unbounded => self.stream_from(vec![]),
+        };
+        let back = match end {
+            Bound::Included(keys) => self.seek_back(keys, true),
+            Bound::Excluded(keys) => self.seek_back(keys, false),
+            Bound::Unbounded => TrieBackWalk {
+                root: Some(self),
+                stack: vec![],
+                nodes: vec![],
+                keys: vec![],
+            },
+        };
+        TrieIter {
+            front,
+            back,
+            remaining: up_to_end.saturating_sub(before_start),
+        }
+    }
+
+    /// Return the `(keys, value)` pair with the largest key sequence at or before `keys`, whether
+    /// or not `keys` itself is stored.
+    ///
+    /// Key elements may be any borrowed form of `K`, as with [`get`](#method.get), as long as it
+    /// is ordered the same way. This resolves a version to the latest release that isn't newer
+    /// than it:
+    ///
+    /// ```
+    /// use trie::OrderedTrie;
+    ///
+    /// let mut releases: OrderedTrie<u32, &str> = OrderedTrie::new(None);
+    /// releases.insert(&[1, 4], "1.4");
+    /// releases.insert(&[1, 4, 2], "1.4.2");
+    /// releases.insert(&[2, 0], "2.0");
+    /// assert_eq!(releases.floor(&[1, 9]).map(|(_, v)| *v), Some("1.4.2"));
+    /// assert_eq!(releases.floor(&[2, 0]).map(|(_, v)| *v), Some("2.0"));
+    /// assert_eq!(releases.floor(&[1]), None);
+    /// ```
+    pub fn floor<Q, I>(&self, keys: I) -> Option<(Vec<&K>, &V)>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.seek_back(keys, true).advance()
+    }
+
+    /// Return the `(keys, value)` pair with the smallest key sequence at or after `keys`, whether
+    /// or not `keys` itself is stored.
+    ///
+    /// Key elements may be any borrowed form of `K`, as with [`floor`](#method.floor).
+    pub fn ceiling<Q, I>(&self, keys: I) -> Option<(Vec<&K>, &V)>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut stream = self.seek_front(keys, true);
+        stream.next().map(|(keys, val)| (keys.to_vec(), val))
+    }
+
+    /// Return the `(keys, value)` pair with the largest key sequence strictly before `keys`, like
+    /// [`floor`](#method.floor) but never `keys` itself.
+    pub fn predecessor<Q, I>(&self, keys: I) -> Option<(Vec<&K>, &V)>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.seek_back(keys, false).advance()
+    }
+
+    /// Return the `(keys, value)` pair with the smallest key sequence strictly after `keys`, like
+    /// [`ceiling`](#method.ceiling) but never `keys` itself.
+    pub fn successor<Q, I>(&self, keys: I) -> Option<(Vec<&K>, &V)>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut stream = self.seek_front(keys, false);
+        stream.next().map(|(keys, val)| (keys.to_vec(), val))
+    }
+
+    // Count the values whose key sequences come before `keys`, and the one at `keys` itself if
+    // `inclusive`
+    fn rank(&self, keys: &[K], inclusive: bool) -> usize {
+        let mut node = self;
+        let mut rank = 0;
+        for k in keys {
+            // A node comes before its descendants, and so do its smaller siblings' subtries
+            rank += node.val.is_some() as usize;
+            let smaller = C::range(&node.children, (Bound::Unbounded, Bound::Excluded(k)));
+            rank += smaller.map(|(_, child)| child.len).sum::<usize>();
+            node = match node.children.get(k) {
+                Some(child) => child,
+                None => return rank,
+            };
+        }
+        if inclusive {
+            rank += node.val.is_some() as usize;
+        }
+        rank
+    }
+
+    // Find the stored key equal to `k` along with its child
+    fn child_entry<'a, Q>(&'a self, k: &Q) -> Option<(&'a K, &'a Trie<K, V, C>)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        C::range(&self.children, (Bound::Included(k), Bound::Included(k))).next()
+    }
+
+    // A forward walk that starts at the first key sequence after `target`, or at `target` itself
+    // if `inclusive`
+    fn seek_front<'a, Q, I>(&'a self, target: I, inclusive: bool) -> TrieStream<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut stream = TrieStream::empty();
+        let mut node = self;
+        for k in target {
+            let k = k.lookup_key();
+            // Everything below the larger siblings of `k` comes after the target
+            let larger = C::range(&node.children, (Bound::Excluded(k), Bound::Unbounded));
+            stream.stack.push(larger);
+            match node.child_entry(k) {
+                Some((key, child)) => {
+                    stream.keys.push(key);
+                    node = child;
+                }
+                None => return stream,
+            }
+        }
+        if inclusive {
+            stream.root = Some(node);
+        } else {
+            stream.stack.push(node.children.iter());
+        }
+        stream
+    }
+
+    // A backward walk that starts at the last key sequence before `target`, or at `target`
+    // itself if `inclusive`
+    fn seek_back<'a, Q, I>(&'a self, target: I, inclusive: bool) -> TrieBackWalk<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: LookupKey<Key = Q>,
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut walk = TrieBackWalk::empty();
+        let mut node = self;
+        for k in target {
+            let k = k.lookup_key();
+            // The node and everything below the smaller siblings of `k` come before the target
+            let smaller = C::range(&node.children, (Bound::Unbounded, Bound::Excluded(k)));
+            walk.stack.push(smaller);
+            walk.nodes.push(node);
+            match node.child_entry(k) {
+                Some((key, child)) => {
+                    walk.keys.push(key);
+                    node = child;
+                }
+                None => return walk,
+            }
+        }
+        if inclusive {
+            // The target's value is next, but none of its descendants, which come after it
+            let none = match node.children.iter().next() {
+                Some((first, _)) => {
+                    C::range::<_, K, _>(&node.children, (Bound::Unbounded, Bound::Excluded(first)))
+                }
+                None => node.children.iter(),
+            };
+            walk.stack.push(none);
+            walk.nodes.push(node);
+        } else {
+            walk.keys.pop();
+        }
+        walk
+    }
+}
+
+impl<K, V, C> Trie<K, V, C>
+where
+    K: Clone,
+    C: Children<K>,
+{
+    /// Store `val` at the node reached by following `keys`, creating nodes as needed.
+    ///
+    /// `keys` may yield key elements either by value or by reference, so a slice, a `Vec` or an
+    /// iterator such as `"word".chars()` can be passed directly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a value is already stored at `keys`. See [`try_insert`](#method.try_insert) for
+    /// a non-panicking version.
+    pub fn insert<I>(&mut self, keys: I, val: V)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        match self.entry(keys) {
+            Entry::Occupied(_) => panic!("Tried to insert into Trie where value already exists"),
+            Entry::Vacant(e) => {
+                e.insert(val);
+            }
+        }
+    }
+
+    /// Store `val` at the node reached by following `keys`, creating nodes as needed, and return
+    /// the value that was previously stored there, if any.
+    ///
+    /// This mirrors `HashMap::insert`: the last value written to a key sequence wins.
+    pub fn replace<I>(&mut self, keys: I, val: V) -> Option<V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        match self.entry(keys) {
+            Entry::Occupied(mut e) => Some(e.insert(val)),
+            Entry::Vacant(e) => {
+                e.insert(val);
+                None
+            }
+        }
+    }
+
+    /// Get the entry for the node reached by following `keys`, for in-place insertion or update.
+    ///
+    /// The key sequence is walked only once, regardless of how the entry is then used.
+    pub fn entry<'a, I>(&'a mut self, keys: I) -> Entry<'a, K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let mut keys = keys.into_iter();
+        let mut node = self;
+        // Value counts of the nodes above `node`, which go up if a value is inserted
+        let mut counts = vec![];
+        // Key elements for which there is no node yet
+        let mut missing = vec![];
+        for k in keys.by_ref() {
+            let k = k.borrow();
+            if !node.children.contains_key(k) {
+                missing.push(k.clone());
+                break;
+            }
+            let Trie { children, len, .. } = node;
+            counts.push(len);
+            node = children.get_mut(k).unwrap();
+        }
+        missing.extend(keys.map(|k| k.borrow().clone()));
+        if missing.is_empty() && node.val.is_some() {
+            Entry::Occupied(OccupiedEntry { node })
+        } else {
+            Entry::Vacant(VacantEntry {
+                counts,
+                node,
+                keys: missing,
+            })
+        }
+    }
+
+    /// Store `val` at the node reached by following `keys`, creating nodes as needed.
+    ///
+    /// If a value is already stored at `keys`, the trie is left unchanged and
+    /// [`TrieError::AlreadyExists`](enum.TrieError.html#variant.AlreadyExists) is returned,
+    /// carrying `val` back to the caller along with the value already stored there.
+    pub fn try_insert<I>(&mut self, keys: I, val: V) -> Result<(), TrieError<'_, V>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        match self.entry(keys) {
+            Entry::Occupied(e) => Err(TrieError::AlreadyExists {
+                value: val,
+                existing: e.into_mut(),
+            }),
+            Entry::Vacant(e) => {
+                e.insert(val);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove every value whose key sequence starts with `keys`, returning an iterator over the
+    /// removed `(keys, value)` pairs.
+    ///
+    /// The entries are detached from the trie immediately, as with
+    /// [`remove_prefix`](#method.remove_prefix). Yielded key sequences include the prefix. Any
+    /// entries not consumed from the iterator are dropped with it.
+    pub fn drain_prefix<I>(&mut self, keys: I) -> TrieDrain<K, V, C>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let keys = keys
+            .into_iter()
+            .map(|k| k.borrow().clone())
+            .collect::<Vec<K>>();
+        TrieDrain {
+            iter: TrieIntoIter {
+                root: self.remove_prefix(&keys),
+                stack: vec![],
+                keys,
+            },
+        }
+    }
+}
+
+impl<K, V, C> Trie<K, V, C>
+where
+    V: Clone,
+    C: Children<K>,
+{
+    /// Return a copy of the value stored at `keys`, if any.
+    ///
+    /// See [`get`](#method.get) to borrow the value instead.
+    pub fn fetch<I>(&self, keys: I) -> Option<V>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<K>,
+    {
+        let mut node = self;
+        for k in keys {
+            node = node.children.get(k.borrow())?;
+        }
+        node.val.clone()
+    }
+}
+
+impl<K, V, C> Debug for Trie<K, V, C>
+where
+    K: Debug,
+    V: Debug,
+    C: Children<K>,
+{
+    /// Format the trie as a flat map from key sequences to values.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, C> Drop for Trie<K, V, C>
+where
+    C: Children<K>,
+{
+    fn drop(&mut self) {
+        // Dropping the child map as is would recurse once per level, so empty out each node's
+        // children before it is dropped
+        if self.children.is_empty() {
+            return;
+        }
+        let mut stack = vec![self.take_children()];
+        while let Some(children) = stack.pop() {
+            for (_, mut child) in children.into_iter() {
+                if !child.children.is_empty() {
+                    stack.push(child.take_children());
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, C> Serialize for Trie<K, V, C>
+where
+    K: Serialize,
+    V: Serialize,
+    C: Children<K>,
+{
+    /// Serialize the trie as a flat sequence: the root's value, followed by a
+    /// `(levels_to_pop, key, value)` record for every other node, depth first.
+    ///
+    /// `levels_to_pop` says how many levels to go back up from the previous node before the
+    /// node is added as a child, so each record holds a single key element and the encoding
+    /// grows with the number of nodes rather than with the total length of the key sequences.
+    /// Values are written as sequences of zero or one elements, so that a node without a value
+    /// can be told apart from a stored `None` or `()`.
+    ///
+    /// This is not the format earlier versions of this crate wrote, which nested every node's
+    /// children inside it, so tries serialized by those versions can't be read back.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Some formats need the length up front, so count the nodes first
+        let mut seq = serializer.serialize_seq(Some(1 + self.records().count()))?;
+        seq.serialize_element(self.val_slice())?;
+        for record in self.records() {
+            seq.serialize_element(&record)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K, V, C> Deserialize<'de> for Trie<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Children<K>,
+    C::Map<Trie<K, V, C>>: Default,
+{
+    /// Deserialize a trie from the sequence written by its `Serialize` implementation.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(TrieVisitor(PhantomData))
+    }
+}
+
+struct TrieVisitor<K, V, C>(PhantomData<(K, V, C)>);
+
+impl<K, V, C> TrieVisitor<K, V, C>
+where
+    C: Children<K>,
+{
+    // Turn a value written as a sequence of zero or one elements back into an Option
+    fn value<E: de::Error>(vals: Vec<V>) -> Result<Option<V>, E> {
+        let mut vals = vals;
+        if vals.len() > 1 {
+            return Err(E::invalid_length(vals.len(), &"at most one value per node"));
+        }
+        Ok(vals.pop())
+    }
+
+    // Add the last node along `path` to its parent, now that all of its children have been added
+    fn finish_last<E: de::Error>(
+        root: &mut Trie<K, V, C>,
+        path: &mut Vec<(K, Trie<K, V, C>)>,
+    ) -> Result<(), E> {
+        let (k, node) = path.pop().unwrap();
+        let parent = match path.last_mut() {
+            Some((_, parent)) => parent,
+            None => root,
+        };
+        parent.len += node.len;
+        match parent.children.insert(k, node) {
+            Some(_) => Err(E::custom("the same key appears twice below a node")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'de, K, V, C> Visitor<'de> for TrieVisitor<K, V, C>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+    C: Children<K>,
+    C::Map<Trie<K, V, C>>: Default,
+{
+    type Value = Trie<K, V, C>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a root value followed by (levels_to_pop, key, value) records"
+        )
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let root = match seq.next_element::<Vec<V>>()? {
+            Some(vals) => Self::value(vals)?,
+            None => return Err(de::Error::invalid_length(0, &self)),
+        };
+        let mut root = Trie::new(root);
+        // The nodes below the root along the path to the latest record, each of which is added
+        // to its parent once all of its own children have been
+        let mut path = vec![];
+        while let Some((pops, k, vals)) = seq.next_element::<(usize, K, Vec<V>)>()? {
+            if pops > path.len() {
+                return Err(de::Error::custom("a record goes up past the root"));
+            }
+            for _ in 0..pops {
+                Self::finish_last(&mut root, &mut path)?;
+            }
+            path.push((k, Trie::new(Self::value(vals)?)));
+        }
+        while !path.is_empty() {
+            Self::finish_last(&mut root, &mut path)?;
+        }
+        Ok(root)
+    }
+}
+
+/// Errors returned by the fallible operations on a [`Trie`](struct.Trie.html).
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrieError<'a, V> {
+    /// A value is already stored at the given key sequence. The stored value is left in place.
+    AlreadyExists {
+        /// The value that was rejected.
+        value: V,
+        /// The value already stored at the key sequence.
+        existing: &'a mut V,
+    },
+}
+
+impl<'a, V> Display for TrieError<'a, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrieError::AlreadyExists { .. } => {
+                write!(f, "Tried to insert into Trie where value already exists")
+            }
+        }
+    }
+}
+
+impl<'a, V: Debug> Error for TrieError<'a, V> {}
+
+impl<K, V, C> Default for Trie<K, V, C>
+where
+    C: Children<K>,
+    C::Map<Trie<K, V, C>>: Default,
+{
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<K, V, C, Q> FromIterator<(Q, V)> for Trie<K, V, C>
+where
+    K: Clone,
+    C: Children<K>,
+    C::Map<Trie<K, V, C>>: Default,
+    Q: IntoIterator,
+    Q::Item: Borrow<K>,
+{
+    /// Build a trie from `(keys, value)` pairs. If a key sequence appears more than once, the
+    /// last value for it is kept.
+    fn from_iter<I: IntoIterator<Item = (Q, V)>>(iter: I) -> Self {
+        let mut t = Self::default();
+        t.extend(iter);
+        t
+    }
+}
+
+impl<K, V, C, Q> Extend<(Q, V)> for Trie<K, V, C>
+where
+    K: Clone,
+    C: Children<K>,
+    Q: IntoIterator,
+    Q::Item: Borrow<K>,
+{
+    /// Store every `(keys, value)` pair, overwriting any value already stored at a key sequence.
+    fn extend<I: IntoIterator<Item = (Q, V)>>(&mut self, iter: I) {
+        for (keys, val) in iter {
+            self.replace(keys, val);
+        }
+    }
+}
+
+impl<K, V, C> IntoIterator for Trie<K, V, C>
+where
+    K: Clone,
+    C: Children<K>,
+{
+    type Item = (Vec<K>, V);
+    type IntoIter = TrieIntoIter<K, V, C>;
+
+    /// Consume the trie, iterating over `(keys, value)` pairs in the same order as
+    /// [`keys`](#method.keys). Values are moved out of the nodes rather than cloned.
+    fn into_iter(self) -> Self::IntoIter {
+        TrieIntoIter {
+            root: Some(self),
+            stack: vec![],
+            keys: vec![],
+        }
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a Trie<K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = (Vec<&'a K>, &'a V);
+    type IntoIter = TrieIter<'a, K, V, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a mut Trie<K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = (Vec<&'a K>, &'a mut V);
+    type IntoIter = TrieIterMut<'a, K, V, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Iterator over the keys of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::keys`](struct.Trie.html#method.keys).
+#[derive(Debug)]
+pub struct TrieKeyIter<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    iter: TrieIter<'a, K, V, C>,
+}
+
+impl<'a, K, V, C> Iterator for TrieKeyIter<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = Vec<&'a K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|n| n.0)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V, C> DoubleEndedIterator for TrieKeyIter<'a, K, V, C>
+where
+    C: OrderedChildren<K>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|n| n.0)
+    }
+}
+
+impl<'a, K, V, C> ExactSizeIterator for TrieKeyIter<'a, K, V, C> where C: Children<K> {}
+
+/// Iterator over the values of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::values`](struct.Trie.html#method.values).
+#[derive(Debug)]
+pub struct TrieValueIter<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    iter: TrieIter<'a, K, V, C>,
+}
+
+impl<'a, K, V, C> Iterator for TrieValueIter<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_value()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K, V, C> DoubleEndedIterator for TrieValueIter<'a, K, V, C>
+where
+    C: OrderedChildren<K>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|n| n.1)
+    }
+}
+
+impl<'a, K, V, C> ExactSizeIterator for TrieValueIter<'a, K, V, C> where C: Children<K> {}
+
+/// Iterator over the `(keys, value)` pairs of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::iter`](struct.Trie.html#method.iter).
+///
+/// Double-ended when the trie's backend keeps siblings sorted.
+#[derive(Debug)]
+pub struct TrieIter<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    front: TrieStream<'a, K, V, C>,
+    back: TrieBackWalk<'a, K, V, C>,
+    // Number of values not yet yielded from either end, so that the two walks never meet
+    remaining: usize,
+}
+
+impl<'a, K, V, C> TrieIter<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    // An iterator with no values left to yield
+    fn empty() -> Self {
+        TrieIter {
+            front: TrieStream::empty(),
+            back: TrieBackWalk::empty(),
+            remaining: 0,
+        }
+    }
+
+    // Values don't need their keys, so skip copying them out
+    fn next_value(&mut self) -> Option<&'a V> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.front.advance()
+    }
+}
+
+impl<'a, K, V, C> Iterator for TrieIter<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = (Vec<&'a K>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let val = self.next_value()?;
+        Some((self.front.keys.clone(), val))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V, C> DoubleEndedIterator for TrieIter<'a, K, V, C>
+where
+    C: OrderedChildren<K>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.back.advance()
+    }
+}
+
+impl<'a, K, V, C> ExactSizeIterator for TrieIter<'a, K, V, C> where C: Children<K> {}
+
+// Walks a trie in the reverse of the order of TrieStream, visiting each node's children from
+// last to first and then the node itself
+struct TrieBackWalk<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    // The first node to visit, until its children have been queued up
+    root: Option<&'a Trie<K, V, C>>,
+    // Iterators over the remaining children of each node along the current path
+    stack: Vec<ChildIter<'a, K, V, C>>,
+    // The nodes along the current path, whose values are visited once their children are done
+    nodes: Vec<&'a Trie<K, V, C>>,
+    // The key sequence leading to the current node
+    keys: Vec<&'a K>,
+}
+
+impl<'a, K, V, C> TrieBackWalk<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    // A walk with no nodes left to visit
+    fn empty() -> Self {
+        TrieBackWalk {
+            root: None,
+            stack: vec![],
+            nodes: vec![],
+            keys: vec![],
+        }
+    }
+}
+
+impl<'a, K, V, C> TrieBackWalk<'a, K, V, C>
+where
+    C: OrderedChildren<K>,
+{
+    fn advance(&mut self) -> Option<(Vec<&'a K>, &'a V)> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children.iter());
+            self.nodes.push(root);
+        }
+        loop {
+            match C::next_back(self.stack.last_mut()?) {
+                Some((k, child)) => {
+                    self.keys.push(k);
+                    self.stack.push(child.children.iter());
+                    self.nodes.push(child);
+                }
+                None => {
+                    // All of the node's children are done, so it's the node's turn
+                    self.stack.pop();
+                    let node = self.nodes.pop().unwrap();
+                    let entry = node.val.as_ref().map(|val| (self.keys.clone(), val));
+                    if !self.stack.is_empty() {
+                        self.keys.pop();
+                    }
+                    if entry.is_some() {
+                        return entry;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, C> Debug for TrieBackWalk<'a, K, V, C>
+where
+    K: Debug,
+    C: Children<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The child map iterators aren't known to be Debug, so this and the other walks' Debug
+        // impls only show where they are
+        f.debug_struct("TrieBackWalk")
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+// Iterator over the children of a node
+type ChildIter<'a, K, V, C> =
+    <<C as Children<K>>::Map<Trie<K, V, C>> as ChildMap<K, Trie<K, V, C>>>::Iter<'a>;
+
+/// Streaming iterator over the `(keys, value)` pairs of a [`Trie`](struct.Trie.html), created
+/// by [`Trie::stream`](struct.Trie.html#method.stream).
+///
+/// Walks the trie depth first with an explicit stack, keeping the key sequence of the current
+/// entry in a single buffer that is lent out by [`next`](#method.next).
+pub struct TrieStream<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    // The first node to visit, until its value has been visited
+    root: Option<&'a Trie<K, V, C>>,
+    // Iterators over the remaining children of each node along the current path
+    stack: Vec<ChildIter<'a, K, V, C>>,
+    // The key sequence leading to the current node
+    keys: Vec<&'a K>,
+}
+
+impl<'a, K, V, C> TrieStream<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    // A stream with no nodes left to visit
+    fn empty() -> Self {
+        TrieStream {
+            root: None,
+            stack: vec![],
+            keys: vec![],
+        }
+    }
+
+    /// Move on to the next entry and return its key sequence and value, or `None` once every
+    /// entry has been visited.
+    // Iterator::next can't return a borrow of the iterator itself
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&[&'a K], &'a V)> {
+        let val = self.advance()?;
+        Some((&self.keys, val))
+    }
+
+    // Move on to the next node with a value, leaving its key sequence in `keys`
+    fn advance(&mut self) -> Option<&'a V> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children.iter());
+            if let Some(val) = &root.val {
+                return Some(val);
+            }
+        }
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some((k, child)) => {
+                    self.keys.push(k);
+                    self.stack.push(child.children.iter());
+                    if let Some(val) = &child.val {
+                        return Some(val);
+                    }
+                }
+                None => {
+                    // Done with this node's children, so go back up to its parent. The first
+                    // node's keys are left alone, as they lead to it from outside the stream.
+                    self.stack.pop();
+                    if !self.stack.is_empty() {
+                        self.keys.pop();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, C> Debug for TrieStream<'a, K, V, C>
+where
+    K: Debug,
+    C: Children<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrieStream")
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+// Walks the nodes below a trie's root depth first, yielding each node's key and value along with
+// the number of levels to go back up from the previously yielded node to reach its parent
+struct TrieRecords<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    // The node whose descendants are walked, until its children have been queued up
+    root: Option<&'a Trie<K, V, C>>,
+    // Iterators over the remaining children of each node along the current path
+    stack: Vec<ChildIter<'a, K, V, C>>,
+    // Levels finished since the last node was yielded
+    pops: usize,
+}
+
+impl<'a, K, V, C> Iterator for TrieRecords<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = (usize, &'a K, &'a [V]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            self.stack.push(root.children.iter());
+        }
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some((k, child)) => {
+                    self.stack.push(child.children.iter());
+                    let pops = mem::replace(&mut self.pops, 0);
+                    return Some((pops, k, child.val_slice()));
+                }
+                None => {
+                    self.stack.pop();
+                    self.pops += 1;
+                }
+            }
+        }
+    }
+}
+
+// Mutable iterator over the children of a node
+type ChildIterMut<'a, K, V, C> =
+    <<C as Children<K>>::Map<Trie<K, V, C>> as ChildMap<K, Trie<K, V, C>>>::IterMut<'a>;
+
+/// Mutable iterator over the `(keys, value)` pairs of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::iter_mut`](struct.Trie.html#method.iter_mut).
+pub struct TrieIterMut<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    // The trie's root, until its value has been visited
+    root: Option<&'a mut Trie<K, V, C>>,
+    // Iterators over the remaining children of each node along the current path
+    stack: Vec<ChildIterMut<'a, K, V, C>>,
+    // The key leading to each node along the current path, so one shorter than `stack`
+    keys: Vec<&'a K>,
+}
+
+impl<'a, K, V, C> Iterator for TrieIterMut<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = (Vec<&'a K>, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            let Trie { val, children, .. } = root;
+            self.stack.push(children.iter_mut());
+            if let Some(val) = val {
+                return Some((vec![], val));
+            }
+        }
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some((k, child)) => {
+                    let Trie { val, children, .. } = child;
+                    self.keys.push(k);
+                    self.stack.push(children.iter_mut());
+                    if let Some(val) = val {
+                        return Some((self.keys.clone(), val));
+                    }
+                }
+                None => {
+                    // Done with this node's children, so go back up to its parent
+                    self.stack.pop();
+                    self.keys.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, C> Debug for TrieIterMut<'a, K, V, C>
+where
+    K: Debug,
+    C: Children<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrieIterMut")
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Mutable iterator over the values of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::values_mut`](struct.Trie.html#method.values_mut).
+#[derive(Debug)]
+pub struct TrieValueIterMut<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    iter: TrieIterMut<'a, K, V, C>,
+}
+
+impl<'a, K, V, C> Iterator for TrieValueIterMut<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|n| n.1)
+    }
+}
+
+/// Iterator over the values stored along a key sequence, created by
+/// [`Trie::prefixes_of`](struct.Trie.html#method.prefixes_of).
+#[derive(Debug)]
+pub struct TriePrefixes<'a, K, V, I, C = HashChildren>
+where
+    C: Children<K>,
+{
+    // The next node along the key sequence, or None once the sequence leaves the trie
+    node: Option<&'a Trie<K, V, C>>,
+    keys: I,
+    // Number of key elements leading to `node`
+    depth: usize,
+}
+
+impl<'a, K, V, Q, I, C> Iterator for TriePrefixes<'a, K, V, I, C>
+where
+    I: Iterator,
+    I::Item: LookupKey<Key = Q>,
+    Q: ?Sized,
+    C: LookupChildren<K, Q>,
+{
+    type Item = (usize, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node?;
+            let depth = self.depth;
+            self.node = match self.keys.next() {
+                Some(k) => C::get(&node.children, k.lookup_key()).map(|(_, child)| child),
+                None => None,
+            };
+            self.depth += 1;
+            if let Some(v) = &node.val {
+                return Some((depth, v));
+            }
+        }
+    }
+}
+
+// Owning iterator over the children of a node
+type ChildIntoIter<K, V, C> =
+    <<C as Children<K>>::Map<Trie<K, V, C>> as ChildMap<K, Trie<K, V, C>>>::IntoIter;
+
+/// Owning iterator over the `(keys, value)` pairs of a [`Trie`](struct.Trie.html), created by
+/// its `IntoIterator` implementation.
+pub struct TrieIntoIter<K, V, C = HashChildren>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    val: Option<V>,
-    children: HashMap<K, Trie<K, V>>,
+    // The trie's root, until its value has been visited
+    root: Option<Trie<K, V, C>>,
+    // Iterators over the remaining children of each node along the current path
+    stack: Vec<ChildIntoIter<K, V, C>>,
+    // The key sequence leading to the current node
+    keys: Vec<K>,
 }
 
-impl<K, V> Trie<K, V>
+impl<K, V, C> Iterator for TrieIntoIter<K, V, C>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    K: Clone,
+    C: Children<K>,
 {
-    fn new(val: Option<V>) -> Self {
-        Self {
-            val,
-            children: HashMap::new(),
-        }
-    }
+    type Item = (Vec<K>, V);
 
-    fn insert(&mut self, keys: &[K], val: V) {
-        if keys.is_empty() {
-            assert!(
-                self.val.is_none(),
-                "Tried to insert into Trie where value already exists"
-            );
-            self.val = Some(val);
-            return;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(mut root) = self.root.take() {
+            let (val, children) = (root.val.take(), root.take_children());
+            self.stack.push(children.into_iter());
+            if let Some(val) = val {
+                return Some((self.keys.clone(), val));
+            }
         }
-        assert!(!keys.is_empty());
-        let (first, remaining) = keys.split_first().unwrap();
-        if self.children.contains_key(first) {
-            self.children.get_mut(first).unwrap().insert(remaining, val);
-        } else {
-            let mut new = Trie::new(None);
-            new.insert(remaining, val);
-            self.children.insert(first.clone(), new);
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some((k, mut child)) => {
+                    let (val, children) = (child.val.take(), child.take_children());
+                    self.keys.push(k);
+                    self.stack.push(children.into_iter());
+                    if let Some(val) = val {
+                        return Some((self.keys.clone(), val));
+                    }
+                }
+                None => {
+                    // Done with this node's children, so go back up to its parent. The root's
+                    // keys are left alone, as they don't belong to any node being visited.
+                    self.stack.pop();
+                    if !self.stack.is_empty() {
+                        self.keys.pop();
+                    }
+                }
+            }
         }
     }
+}
 
-    fn fetch(&self, keys: &[K]) -> Option<V> {
-        if keys.is_empty() {
-            return self.val.clone();
-        }
-        assert!(!keys.is_empty());
-        let (first, remaining) = keys.split_first().unwrap();
-        if self.children.contains_key(first) {
-            self.children[first].fetch(remaining)
-        } else {
-            None
-        }
+impl<K, V, C> Debug for TrieIntoIter<K, V, C>
+where
+    K: Debug,
+    C: Children<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrieIntoIter")
+            .field("keys", &self.keys)
+            .finish_non_exhaustive()
     }
+}
 
-    fn keys<'a>(&'a self) -> TrieKeyIter<'a, K, V> {
-        TrieKeyIter {
-            iter: self.iter_impl(&[]),
-        }
+/// Owning iterator over entries removed from a [`Trie`](struct.Trie.html), created by
+/// [`Trie::drain_prefix`](struct.Trie.html#method.drain_prefix).
+#[derive(Debug)]
+pub struct TrieDrain<K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    // Iterates over the detached subtrie, with the prefix as the root's keys
+    iter: TrieIntoIter<K, V, C>,
+}
+
+impl<K, V, C> Iterator for TrieDrain<K, V, C>
+where
+    K: Clone,
+    C: Children<K>,
+{
+    type Item = (Vec<K>, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
     }
+}
 
-    fn values<'a>(&'a self) -> TrieValueIter<'a, K, V> {
-        TrieValueIter {
-            iter: self.iter_impl(&[]),
-        }
+/// A mutable view of the subtrie below a prefix, created by
+/// [`Trie::subtrie_mut`](struct.Trie.html#method.subtrie_mut).
+///
+/// Dereferences to the subtrie, which is taken out of the trie while the view is alive. When the
+/// view is dropped, the subtrie is put back and the value counts of the nodes above it are
+/// adjusted for any values inserted into or removed from it.
+///
+/// If the view is leaked, for example with `mem::forget`, the subtrie is lost along with its
+/// values, much as the elements of a leaked `vec::Drain` are, but the rest of the trie is left
+/// consistent.
+#[derive(Debug)]
+pub struct SubtrieMut<'a, K, V, C = HashChildren>
+where
+    C: Children<K>,
+{
+    // Value counts of the nodes above `slot`, which leave out the subtrie while it is taken
+    counts: Vec<&'a mut usize>,
+    // The empty node left in place of the subtrie
+    slot: &'a mut Trie<K, V, C>,
+    sub: Trie<K, V, C>,
+}
+
+impl<'a, K, V, C> Deref for SubtrieMut<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    type Target = Trie<K, V, C>;
+
+    fn deref(&self) -> &Trie<K, V, C> {
+        &self.sub
     }
+}
 
-    fn iter<'a>(&'a self) -> TrieIter<'a, K, V> {
-        self.iter_impl(&[])
+impl<'a, K, V, C> DerefMut for SubtrieMut<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    fn deref_mut(&mut self) -> &mut Trie<K, V, C> {
+        &mut self.sub
     }
+}
 
-    fn iter_impl<'a>(&'a self, keys_above: &[&'a K]) -> TrieIter<'a, K, V> {
-        TrieIter {
-            inner: self,
-            child_iters: None,
-            current: 0,
-            did_self: false,
-            keys_above: keys_above.to_vec(),
+impl<'a, K, V, C> Drop for SubtrieMut<'a, K, V, C>
+where
+    C: Children<K>,
+{
+    fn drop(&mut self) {
+        for len in self.counts.iter_mut() {
+            **len += self.sub.len;
         }
+        let empty = self.sub.empty_like();
+        *self.slot = mem::replace(&mut self.sub, empty);
     }
 }
 
+/// A view into a single node of a [`Trie`](struct.Trie.html), created by
+/// [`Trie::entry`](struct.Trie.html#method.entry).
 #[derive(Debug)]
-struct TrieKeyIter<'a, K, V>
+pub enum Entry<'a, K, V, C = HashChildren>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    iter: TrieIter<'a, K, V>,
+    /// The key sequence has a value stored at it.
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    /// The key sequence has no value stored at it.
+    Vacant(VacantEntry<'a, K, V, C>),
 }
 
-impl<'a, K, V> Iterator for TrieKeyIter<'a, K, V>
+impl<'a, K, V, C> Entry<'a, K, V, C>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    type Item = Vec<&'a K>;
+    /// Store `default` if the entry is vacant, and return a mutable reference to the value.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            None => None,
-            Some(n) => Some(n.0),
+    /// Store the result of `default` if the entry is vacant, and return a mutable reference to
+    /// the value.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    /// Call `f` on the value if the entry is occupied, then return the entry.
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(e) = &mut self {
+            f(e.get_mut());
         }
+        self
+    }
+}
+
+impl<'a, K, V, C> Entry<'a, K, V, C>
+where
+    C: Children<K>,
+    V: Default,
+{
+    /// Store `V::default()` if the entry is vacant, and return a mutable reference to the value.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
     }
 }
 
+/// An occupied [`Entry`](enum.Entry.html).
 #[derive(Debug)]
-struct TrieValueIter<'a, K, V>
+pub struct OccupiedEntry<'a, K, V, C = HashChildren>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    iter: TrieIter<'a, K, V>,
+    // Always has a value
+    node: &'a mut Trie<K, V, C>,
 }
 
-impl<'a, K, V> Iterator for TrieValueIter<'a, K, V>
+impl<'a, K, V, C> OccupiedEntry<'a, K, V, C>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    type Item = V;
+    /// Return a reference to the stored value.
+    pub fn get(&self) -> &V {
+        self.node.val.as_ref().unwrap()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.iter.next() {
-            None => None,
-            Some(n) => Some(n.1),
-        }
+    /// Return a mutable reference to the stored value.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.node.val.as_mut().unwrap()
+    }
+
+    /// Convert the entry into a mutable reference to the stored value that lives as long as the
+    /// borrow of the trie.
+    pub fn into_mut(self) -> &'a mut V {
+        self.node.val.as_mut().unwrap()
+    }
+
+    /// Store `val`, returning the value that was stored before.
+    pub fn insert(&mut self, val: V) -> V {
+        std::mem::replace(self.get_mut(), val)
     }
 }
 
+/// A vacant [`Entry`](enum.Entry.html).
 #[derive(Debug)]
-struct TrieIter<'a, K, V>
+pub struct VacantEntry<'a, K, V, C = HashChildren>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    inner: &'a Trie<K, V>,
-    child_iters: Option<Vec<Self>>,
-    current: usize,
-    did_self: bool,
-    keys_above: Vec<&'a K>,
+    // Value counts of the nodes above `node`
+    counts: Vec<&'a mut usize>,
+    // The deepest existing node along the key sequence
+    node: &'a mut Trie<K, V, C>,
+    // The rest of the key sequence, for which nodes still need to be created
+    keys: Vec<K>,
 }
 
-impl<'a, K, V> Iterator for TrieIter<'a, K, V>
+impl<'a, K, V, C> VacantEntry<'a, K, V, C>
 where
-    K: Eq + Hash + Debug + Clone,
-    V: Debug + Clone,
+    C: Children<K>,
 {
-    type Item = (Vec<&'a K>, V);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        // If we haven't done ourself yet, then we need to build up a vector of iters from our
-        // children, and also return our own value, if we have one.
-        if !self.did_self {
-            // Make sure we only come in here once
-            self.did_self = true;
-            // If we have children, then we need to build a vector of their iters
-            if !self.inner.children.is_empty() {
-                assert!(self.child_iters.is_none());
-                assert!(self.current == 0);
-                // Get all children Tries
-                let child_keys_iter = self.inner.children.keys();
-                // Turn iter of Tries into iter of TrieIters
-                let child_iters = child_keys_iter.map(|k| {
-                    self.keys_above.push(k);
-                    let i = self.inner.children[k].iter_impl(&self.keys_above);
-                    self.keys_above.pop();
-                    i
-                });
-                // Collect and store
-                let v = child_iters.collect::<Vec<TrieIter<'a, K, V>>>();
-                self.child_iters = Some(v);
-            }
-            // Now that we are done storing iters for our children, we should return our own value,
-            // if any.
-            if self.inner.val.is_some() {
-                return Some((self.keys_above.clone(), self.inner.val.clone().unwrap()));
-            }
-        }
-        assert!(self.did_self);
-        // We must have done ourself, so if we didn't collect some child iters, we must not have
-        // any children and are done
-        if self.child_iters.is_none() {
-            return None;
+    /// Store `val`, creating any missing nodes, and return a mutable reference to it.
+    pub fn insert(self, val: V) -> &'a mut V {
+        for len in self.counts {
+            *len += 1;
         }
-        // Otherwise, we have children and need to return values from them.
-        loop {
-            // Get the next value from the current child
-            let n = self.child_iters.as_mut().unwrap()[self.current].next();
-            // And return it if it exists
-            if n.is_some() {
-                return n;
-            }
-            // If the current child has no more values, then go to the next child
-            if n.is_none() {
-                self.current += 1;
-                // If moving to the next child pushes us past our last child, then we are
-                // completely done
-                if self.current >= self.child_iters.as_ref().unwrap().len() {
-                    return None;
-                }
-            }
+        let mut node = self.node;
+        for k in self.keys {
+            node.len += 1;
+            let child = node.empty_like();
+            node = node.children.get_or_insert_with(k, || child);
         }
+        node.len += 1;
+        node.val = Some(val);
+        node.val.as_mut().unwrap()
     }
 }
 
 #[cfg(test)]
+// Most tests pass key slices by reference, as callers that already hold a `&[K]` do
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
-    use super::Trie;
-    use serde_cbor;
+    use super::{
+        BTreeChildren, ChildMap, Children, Entry, HashChildren, InlineChildren, LookupChildren,
+        OrderedChildren, OrderedTrie, SortedVecChildren, Trie, TrieError,
+    };
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use std::io::{self, BufRead};
+    use std::ops::Bound;
 
     #[test]
     #[should_panic(expected = "Tried to insert into Trie where value already exists")]
@@ -229,6 +2070,22 @@ mod tests {
         t.insert(&[1], 2);
     }
 
+    #[test]
+    fn try_insert_duplicate() {
+        let mut t: Trie<i32, i32> = Trie::new(None);
+        assert_eq!(t.try_insert(&[1, 2], 12), Ok(()));
+        assert_eq!(
+            t.try_insert(&[1, 2], 99),
+            Err(TrieError::AlreadyExists {
+                value: 99,
+                existing: &mut 12
+            })
+        );
+        assert_eq!(t.fetch(&[1, 2]), Some(12));
+        assert_eq!(t.try_insert(&[1], 1), Ok(()));
+        assert_eq!(t.fetch(&[1]), Some(1));
+    }
+
     #[test]
     fn single_key_value() {
         let mut t: Trie<i32, i32> = Trie::new(None);
@@ -264,16 +2121,25 @@ mod tests {
         t
     }
 
+    // The paths in testdata.txt, split into their components, with the line numbers as values
+    fn testdata_trie() -> OrderedTrie<&'static str, usize> {
+        include_str!("../testdata.txt")
+            .lines()
+            .map(|l| l.split('/').collect::<Vec<_>>())
+            .zip(0..)
+            .collect()
+    }
+
     #[test]
     fn iter_order() {
         let t = iter_test_data();
         let items = t.iter().collect::<Vec<_>>();
-        let pos_1 = items.iter().position(|k| k == &(vec![&1i32], 1i32)).unwrap();
-        let pos_11 = items.iter().position(|k| k == &(vec![&1i32, &1], 11i32)).unwrap();
-        let pos_12 = items.iter().position(|k| k == &(vec![&1i32, &2], 12i32)).unwrap();
-        let pos_121 = items.iter().position(|k| k == &(vec![&1i32, &2, &1], 121i32)).unwrap();
-        let pos_122 = items.iter().position(|k| k == &(vec![&1i32, &2, &2], 122i32)).unwrap();
-        let pos_13111 = items.iter().position(|k| k == &(vec![&1i32, &3, &1, &1, &1], 13111i32)).unwrap();
+        let pos_1 = items.iter().position(|k| k == &(vec![&1i32], &1i32)).unwrap();
+        let pos_11 = items.iter().position(|k| k == &(vec![&1i32, &1], &11i32)).unwrap();
+        let pos_12 = items.iter().position(|k| k == &(vec![&1i32, &2], &12i32)).unwrap();
+        let pos_121 = items.iter().position(|k| k == &(vec![&1i32, &2, &1], &121i32)).unwrap();
+        let pos_122 = items.iter().position(|k| k == &(vec![&1i32, &2, &2], &122i32)).unwrap();
+        let pos_13111 = items.iter().position(|k| k == &(vec![&1i32, &3, &1, &1, &1], &13111i32)).unwrap();
         assert!(pos_1 == 0);
         assert!(pos_11 > pos_1);
         assert!(pos_12 > pos_1);
@@ -304,12 +2170,12 @@ mod tests {
     fn iter_value_order() {
         let t = iter_test_data();
         let vals = t.values().collect::<Vec<_>>();
-        let pos_1 = vals.iter().position(|v| v == &1i32).unwrap();
-        let pos_11 = vals.iter().position(|v| v == &11i32).unwrap();
-        let pos_12 = vals.iter().position(|v| v == &12i32).unwrap();
-        let pos_121 = vals.iter().position(|v| v == &121i32).unwrap();
-        let pos_122 = vals.iter().position(|v| v == &122i32).unwrap();
-        let pos_13111 = vals.iter().position(|v| v == &13111i32).unwrap();
+        let pos_1 = vals.iter().position(|v| v == &&1i32).unwrap();
+        let pos_11 = vals.iter().position(|v| v == &&11i32).unwrap();
+        let pos_12 = vals.iter().position(|v| v == &&12i32).unwrap();
+        let pos_121 = vals.iter().position(|v| v == &&121i32).unwrap();
+        let pos_122 = vals.iter().position(|v| v == &&122i32).unwrap();
+        let pos_13111 = vals.iter().position(|v| v == &&13111i32).unwrap();
         assert!(pos_1 == 0);
         assert!(pos_11 > pos_1);
         assert!(pos_12 > pos_1);
@@ -319,13 +2185,807 @@ mod tests {
     }
 
     #[test]
-    /// assert that serde still can't tell the difference between None and Some(())
+    fn from_iter_and_extend() {
+        let mut t: Trie<i32, i32> = vec![(vec![1], 1), (vec![1, 2], 12)].into_iter().collect();
+        t.extend(vec![(vec![2], 2)]);
+        assert_eq!(t.fetch(&[1]), Some(1));
+        assert_eq!(t.fetch(&[1, 2]), Some(12));
+        assert_eq!(t.fetch(&[2]), Some(2));
+        assert_eq!(t.fetch(&[]), None);
+    }
+
+    #[test]
+    fn replace_returns_old() {
+        let mut t: Trie<i32, i32> = Trie::new(None);
+        assert_eq!(t.replace(&[1, 2], 12), None);
+        assert_eq!(t.replace(&[1, 2], 99), Some(12));
+        assert_eq!(t.replace(&[], 0), None);
+        assert_eq!(t.fetch(&[1, 2]), Some(99));
+        assert_eq!(t.fetch(&[]), Some(0));
+        let t: Trie<i32, i32> = vec![(vec![1], 1), (vec![1], 2)].into_iter().collect();
+        assert_eq!(t.fetch(&[1]), Some(2));
+    }
+
+    #[test]
+    fn remove_prunes_empty_nodes() {
+        let mut t = iter_test_data();
+        assert_eq!(t.remove(&[1, 3, 1, 1, 1]), Some(13111));
+        assert_eq!(t.remove(&[1, 3, 1, 1, 1]), None);
+        assert!(!t.children[&1].children.contains_key(&3));
+        assert_eq!(t.remove(&[1, 2]), Some(12));
+        assert_eq!(t.fetch(&[1, 2, 1]), Some(121));
+        assert_eq!(t.remove(&[1, 2, 1]), Some(121));
+        assert_eq!(t.remove(&[1, 2, 2]), Some(122));
+        assert!(!t.children[&1].children.contains_key(&2));
+        assert_eq!(t.remove(&[1, 1]), Some(11));
+        assert_eq!(t.remove(&[1]), Some(1));
+        assert!(t.children.is_empty());
+        assert_eq!(t.remove(&[7, 7]), None);
+    }
+
+    #[test]
+    fn remove_prefix_detaches_subtrie() {
+        let mut t = iter_test_data();
+        let sub = t.remove_prefix(&[1, 2]).unwrap();
+        assert_eq!(sub.fetch(&[]), Some(12));
+        assert_eq!(sub.fetch(&[1]), Some(121));
+        assert_eq!(sub.fetch(&[2]), Some(122));
+        assert_eq!(t.fetch(&[1, 2]), None);
+        assert_eq!(t.fetch(&[1, 2, 1]), None);
+        assert_eq!(t.fetch(&[1, 1]), Some(11));
+        assert!(t.remove_prefix(&[1, 2]).is_none());
+        // Removing the only value below [1, 3] must prune the whole branch
+        let sub = t.remove_prefix(&[1, 3, 1]).unwrap();
+        assert_eq!(sub.fetch(&[1, 1]), Some(13111));
+        assert!(!t.children[&1].children.contains_key(&3));
+        let all = t.remove_prefix(&[]).unwrap();
+        assert_eq!(all.fetch(&[1]), Some(1));
+        assert!(t.children.is_empty());
+    }
+
+    #[test]
+    fn drain_prefix_yields_full_keys() {
+        let mut t = iter_test_data();
+        let mut drained = t.drain_prefix(&[1, 2]).collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(
+            drained,
+            vec![(vec![1, 2], 12), (vec![1, 2, 1], 121), (vec![1, 2, 2], 122)]
+        );
+        assert_eq!(t.fetch(&[1, 2, 2]), None);
+        assert_eq!(t.fetch(&[1]), Some(1));
+        assert_eq!(t.drain_prefix(&[5]).count(), 0);
+    }
+
+    #[test]
+    fn entry_counts_ngrams() {
+        let words = ["a", "b", "a", "b", "c", "a", "b"];
+        let mut t: Trie<&str, u32> = Trie::new(None);
+        for w in words.windows(2) {
+            *t.entry(w).or_insert(0) += 1;
+        }
+        assert_eq!(t.fetch(&["a", "b"]), Some(3));
+        assert_eq!(t.fetch(&["b", "a"]), Some(1));
+        assert_eq!(t.fetch(&["b", "c"]), Some(1));
+        assert_eq!(t.fetch(&["a"]), None);
+    }
+
+    #[test]
+    fn entry_variants() {
+        let mut t = iter_test_data();
+        match t.entry(&[1, 2]) {
+            Entry::Occupied(mut e) => {
+                assert_eq!(e.get(), &12);
+                assert_eq!(e.insert(13), 12);
+            }
+            Entry::Vacant(_) => panic!("[1, 2] should be occupied"),
+        }
+        // [1, 3] exists as a node but holds no value
+        assert!(matches!(t.entry(&[1, 3]), Entry::Vacant(_)));
+        t.entry(&[1, 3])
+            .and_modify(|v| *v += 1)
+            .or_insert_with(|| 13);
+        t.entry(&[1, 2]).and_modify(|v| *v += 1).or_insert(0);
+        *t.entry(&[4, 4, 4]).or_default() += 444;
+        assert_eq!(t.fetch(&[1, 3]), Some(13));
+        assert_eq!(t.fetch(&[1, 2]), Some(14));
+        assert_eq!(t.fetch(&[4, 4, 4]), Some(444));
+        assert_eq!(t.fetch(&[4, 4]), None);
+    }
+
+    #[test]
+    fn get_borrows_value() {
+        let mut t = iter_test_data();
+        assert_eq!(t.get(&[1, 2, 1]), Some(&121));
+        assert_eq!(t.get(&[1, 3]), None);
+        assert_eq!(t.get(&[9]), None);
+        *t.get_mut(&[1, 2, 1]).unwrap() += 1;
+        assert_eq!(t.get(&[1, 2, 1]), Some(&122));
+        assert!(t.get_mut(&[1, 3, 1]).is_none());
+        assert!(t.contains_key(&[1, 3, 1, 1, 1]));
+        assert!(!t.contains_key(&[1, 3, 1, 1]));
+        assert!(!t.contains_key(&[]));
+    }
+
+    #[test]
+    fn non_clone_values() {
+        // Neither Debug nor Clone
+        struct Handle(u32);
+        let mut t: Trie<u8, Handle> = Trie::new(None);
+        t.insert(&[1, 2], Handle(12));
+        assert_eq!(t.replace(&[1, 2], Handle(13)).map(|h| h.0), Some(12));
+        match t.try_insert(&[1, 2], Handle(99)) {
+            Err(TrieError::AlreadyExists { value, existing }) => {
+                assert_eq!(value.0, 99);
+                assert_eq!(existing.0, 13);
+            }
+            Ok(()) => panic!("[1, 2] should be occupied"),
+        }
+        assert!(t.try_insert(&[2], Handle(0)).is_ok());
+        assert_eq!(t.remove(&[2]).map(|h| h.0), Some(0));
+        t.entry(&[1]).or_insert(Handle(1)).0 += 1;
+        assert_eq!(t.get(&[1]).map(|h| h.0), Some(2));
+        assert_eq!(t.values().map(|h| h.0).sum::<u32>(), 15);
+        assert_eq!(t.remove(&[1, 2]).map(|h| h.0), Some(13));
+    }
+
+    #[test]
+    fn into_iter_owned() {
+        // Values are moved out, so they don't need to be Clone
+        struct Handle(i32);
+        let mut t: OrderedTrie<i32, Handle> = Trie::new(Some(Handle(0)));
+        for (k, v) in &iter_test_data() {
+            t.insert(k, Handle(*v));
+        }
+        let expected = t
+            .iter()
+            .map(|(k, v)| (k.into_iter().cloned().collect::<Vec<_>>(), v.0))
+            .collect::<Vec<_>>();
+        let items = t.into_iter().map(|(k, v)| (k, v.0)).collect::<Vec<_>>();
+        assert_eq!(items, expected);
+        assert_eq!(items[0], (vec![], 0));
+        assert_eq!(items.last(), Some(&(vec![1, 3, 1, 1, 1], 13111)));
+
+        let t: Trie<i32, i32> = Trie::new(None);
+        assert_eq!(t.into_iter().next(), None);
+    }
+
+    #[test]
+    fn get_borrowed_key_forms() {
+        let mut t: Trie<String, u32> = Trie::new(None);
+        t.insert(&["src".to_string()], 1);
+        t.insert(&["src".to_string(), "lib.rs".to_string()], 2);
+        assert_eq!(t.get(vec!["src"]), Some(&1));
+        assert_eq!(t.get("src/lib.rs".split('/')), Some(&2));
+        assert_eq!(t.get("src/main.rs".split('/')), None);
+        assert!(t.contains_key(["src", "lib.rs"].iter().copied()));
+        // Segments held in a slice yield `&&str`, one reference too many
+        let segs: &[&str] = &["src", "lib.rs"];
+        assert_eq!(t.get(segs.iter().copied()), Some(&2));
+        assert_eq!(t.subtrie(segs[..1].iter().copied()).unwrap().len(), 2);
+        assert_eq!(t.prefixes_of(segs.iter().copied()).count(), 2);
+        *t.get_mut(vec!["src", "lib.rs"]).unwrap() += 1;
+        assert_eq!(t.fetch(&["src".to_string(), "lib.rs".to_string()]), Some(3));
+    }
+
+    #[test]
+    fn iterator_keys() {
+        let mut t: Trie<char, u32> = Trie::new(None);
+        t.insert("hello".chars(), 1);
+        assert_eq!(t.replace("help".chars(), 2), None);
+        assert_eq!(t.try_insert("he".chars(), 3), Ok(()));
+        *t.entry("hello".chars()).or_insert(0) += 10;
+        assert_eq!(t.fetch("hello".chars()), Some(11));
+        assert_eq!(t.fetch("hel".chars()), None);
+        assert_eq!(t.get("hello".chars()), Some(&11));
+        *t.get_mut("he".chars()).unwrap() += 1;
+        assert!(t.contains_key("he".chars()));
+        assert_eq!(t.subtrie("hel".chars()).unwrap().len(), 2);
+        assert_eq!(t.longest_prefix_match("hex".chars()), Some((2, &4)));
+        assert_eq!(t.remove("help".chars()), Some(2));
+        assert_eq!(
+            t.remove_prefix("he".chars()).unwrap().fetch("llo".chars()),
+            Some(11)
+        );
+        let mut t: Trie<String, u32> = vec![("a/b", 1), ("a/c", 2)]
+            .into_iter()
+            .map(|(p, v)| (p.split('/').map(String::from), v))
+            .collect();
+        assert_eq!(t.get("a/c".split('/').map(String::from)), Some(&2));
+        // Lines of a reader can't be walked twice
+        let lines = io::Cursor::new("b\nd\n").lines().map(|l| l.unwrap());
+        t.insert(lines, 3);
+        assert_eq!(t.get(vec!["b", "d"]), Some(&3));
+        let mut drained = t
+            .drain_prefix(vec!["a".to_string()])
+            .map(|(k, v)| (k.join("/"), v))
+            .collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(
+            drained,
+            vec![("a/b".to_string(), 1), ("a/c".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn into_iter_ref() {
+        let t = iter_test_data();
+        let mut n = 0;
+        for (keys, val) in &t {
+            assert_eq!(
+                t.get(&keys.into_iter().cloned().collect::<Vec<_>>()),
+                Some(val)
+            );
+            n += 1;
+        }
+        assert_eq!(n, 6);
+    }
+
+    #[test]
+    fn ordered_iter_is_sorted() {
+        let mut t: OrderedTrie<i32, i32> = Trie::new(None);
+        for (keys, val) in iter_test_data().iter() {
+            t.insert(keys, *val);
+        }
+        t.insert(&[0, 9], 9);
+        let keys = t.keys().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                vec![&0, &9],
+                vec![&1],
+                vec![&1, &1],
+                vec![&1, &2],
+                vec![&1, &2, &1],
+                vec![&1, &2, &2],
+                vec![&1, &3, &1, &1, &1],
+            ]
+        );
+        let vals = t.values().cloned().collect::<Vec<_>>();
+        assert_eq!(vals, vec![9, 1, 11, 12, 121, 122, 13111]);
+    }
+
+    #[test]
+    fn ordered_testdata() {
+        let t = testdata_trie();
+        let mut sorted = include_str!("../testdata.txt")
+            .lines()
+            .map(|l| l.split('/').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        sorted.sort();
+        let keys = t
+            .keys()
+            .map(|k| k.into_iter().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, sorted);
+    }
+
+    #[test]
+    fn iter_prefix() {
+        let t = iter_test_data();
+        let mut items = t.iter_prefix(&[1, 2]).collect::<Vec<_>>();
+        items.sort();
+        assert_eq!(
+            items,
+            [
+                (vec![&1, &2], &12),
+                (vec![&1, &2, &1], &121),
+                (vec![&1, &2, &2], &122)
+            ]
+        );
+        // The prefix does not need a value of its own
+        assert_eq!(
+            t.keys_with_prefix(&[1, 3]).collect::<Vec<_>>(),
+            [vec![&1, &3, &1, &1, &1]]
+        );
+        assert_eq!(t.values_with_prefix(&[]).count(), t.iter().count());
+        assert_eq!(t.iter_prefix(&[1, 4]).next(), None);
+        assert_eq!(t.iter_prefix(&[1, 2, 1, 1]).next(), None);
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        let mut t = iter_test_data();
+        assert_eq!(t.longest_prefix_match(&[1, 2, 1, 5]), Some((3, &121)));
+        assert_eq!(t.longest_prefix_match(&[1, 2]), Some((2, &12)));
+        // [1, 3] has no value of its own
+        assert_eq!(t.longest_prefix_match(&[1, 3, 1]), Some((1, &1)));
+        assert_eq!(t.longest_prefix_match(&[2]), None);
+        assert_eq!(t.longest_prefix_match(&[]), None);
+        t.insert(&[], 0);
+        assert_eq!(t.longest_prefix_match(&[2]), Some((0, &0)));
+    }
+
+    #[test]
+    fn prefixes_of() {
+        let mut t = iter_test_data();
+        assert_eq!(
+            t.prefixes_of(&[1, 2, 1, 5]).collect::<Vec<_>>(),
+            [(1, &1), (2, &12), (3, &121)]
+        );
+        assert_eq!(
+            t.prefixes_of(&[1, 3, 1, 1, 1]).collect::<Vec<_>>(),
+            [(1, &1), (5, &13111)]
+        );
+        assert_eq!(t.prefixes_of(&[2, 1]).next(), None);
+        t.insert(&[], 0);
+        assert_eq!(t.prefixes_of(&[]).collect::<Vec<_>>(), [(0, &0)]);
+    }
+
+    #[test]
+    fn subtrie() {
+        let mut t = iter_test_data();
+        let sub = t.subtrie(&[1, 2]).unwrap();
+        assert_eq!(sub.get(&[]), Some(&12));
+        assert_eq!(sub.fetch(&[1]), Some(121));
+        let mut keys = sub.keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, [vec![], vec![&1], vec![&2]]);
+        assert!(t.subtrie(&[1, 4]).is_none());
+
+        let mut sub = t.subtrie_mut(&[1, 3]).unwrap();
+        sub.insert(&[2], 132);
+        *sub.get_mut(&[1, 1, 1]).unwrap() += 1;
+        drop(sub);
+        assert_eq!(t.get(&[1, 3, 2]), Some(&132));
+        assert_eq!(t.get(&[1, 3, 1, 1, 1]), Some(&13112));
+    }
+
+    #[test]
+    fn subtrie_mut_leaked() {
+        let mut t: OrderedTrie<i32, i32> = Trie::new(None);
+        t.insert(&[1, 2], 12);
+        t.insert(&[2], 2);
+        let mut sub = t.subtrie_mut(&[1]).unwrap();
+        sub.insert(&[3], 13);
+        std::mem::forget(sub);
+        // The subtrie is lost, but what is left is still counted and iterated correctly
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.iter().collect::<Vec<_>>(), [(vec![&2], &2)]);
+        assert_eq!(t.iter().rev().count(), 1);
+        assert_eq!(t.get(&[1, 2]), None);
+        t.insert(&[1, 2], 12);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.keys().count(), 2);
+    }
+
+    #[test]
+    fn len_tracks_values() {
+        let mut t = iter_test_data();
+        assert_eq!(t.len(), 6);
+        assert_eq!(t.subtrie(&[1, 2]).unwrap().len(), 3);
+        t.replace(&[1, 2], 0);
+        t.insert(&[], 0);
+        *t.entry(&[2, 2]).or_default() += 1;
+        assert_eq!(t.len(), 8);
+        assert_eq!(t.remove(&[1, 2, 1]), Some(121));
+        assert_eq!(t.remove(&[1, 2, 1]), None);
+        assert_eq!(t.remove(&[1, 3]), None);
+        assert_eq!(t.len(), 7);
+        assert_eq!(t.subtrie(&[1]).unwrap().len(), 5);
+
+        let mut sub = t.subtrie_mut(&[1, 3]).unwrap();
+        sub.insert(&[2], 132);
+        sub.insert(&[3], 133);
+        sub.remove(&[1, 1, 1]);
+        assert_eq!(sub.len(), 2);
+        drop(sub);
+        assert_eq!(t.len(), 8);
+        assert_eq!(t.subtrie(&[1]).unwrap().len(), 6);
+
+        assert_eq!(t.remove_prefix(&[1, 2]).unwrap().len(), 2);
+        assert_eq!(t.len(), 6);
+        assert_eq!(t.drain_prefix(&[1]).count(), 4);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.remove_prefix(&[]).unwrap().len(), 2);
+        assert!(t.is_empty());
+
+        let t: OrderedTrie<i32, i32> = iter_test_data().iter().map(|(k, v)| (k, *v)).collect();
+        let encoded = serde_cbor::to_vec(&t).unwrap();
+        let out: OrderedTrie<i32, i32> = serde_cbor::de::from_slice(&encoded).unwrap();
+        assert_eq!(out.len(), 6);
+        assert_eq!(out.subtrie(&[1, 3]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut t = iter_test_data();
+        t.insert(&[], 0);
+        for (k, v) in t.iter_mut() {
+            *v = k.len() as i32;
+        }
+        let mut items = t
+            .iter()
+            .map(|(k, v)| (k.len() as i32, *v))
+            .collect::<Vec<_>>();
+        items.sort();
+        assert_eq!(items.len(), 7);
+        assert!(items.iter().all(|(len, v)| len == v));
+        assert_eq!(t.get(&[1, 3, 1, 1, 1]), Some(&5));
+
+        let mut empty: Trie<i32, i32> = Trie::new(None);
+        assert!(empty.iter_mut().next().is_none());
+        assert!(empty.values_mut().next().is_none());
+    }
+
+    #[test]
+    fn stream_matches_iter() {
+        let t = testdata_trie();
+        let mut streamed = vec![];
+        let mut stream = t.stream();
+        while let Some((keys, val)) = stream.next() {
+            streamed.push((keys.to_vec(), val));
+        }
+        assert_eq!(streamed, t.iter().collect::<Vec<_>>());
+        assert_eq!(streamed.len(), t.len());
+        assert!(stream.next().is_none());
+
+        let mut t = iter_test_data();
+        t.insert(&[], 0);
+        let mut stream = t.stream();
+        assert_eq!(stream.next(), Some((&[] as &[&i32], &0)));
+        assert_eq!(t.values().count(), 7);
+    }
+
+    #[test]
+    fn deep_keys() {
+        // Deep enough to overflow the stack if anything recursed once per key element
+        let deep = vec![7u8; 200_000];
+        let mut t: Trie<u8, usize> = Trie::new(None);
+        t.insert(&deep, 1);
+        t.insert(&deep[..1000], 2);
+        assert_eq!(t.fetch(&deep), Some(1));
+        assert_eq!(t.get(&deep[..1000]), Some(&2));
+        assert_eq!(t.longest_prefix_match(&deep[..5000]), Some((1000, &2)));
+        let lens = t.keys().map(|k| k.len()).collect::<Vec<_>>();
+        assert_eq!(lens, [1000, 200_000]);
+
+        let encoded = serde_cbor::to_vec(&t).unwrap();
+        let mut out: Trie<u8, usize> = serde_cbor::de::from_slice(&encoded).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out.remove(&deep), Some(1));
+        assert_eq!(out.subtrie(&deep[..1000]).unwrap().len(), 1);
+        assert_eq!(out.remove_prefix(&deep[..10]).unwrap().len(), 1);
+        assert!(out.is_empty());
+        assert_eq!(t.into_iter().count(), 2);
+    }
+
+    #[test]
+    fn reverse_iteration() {
+        let t = testdata_trie();
+        let mut forward = t.iter().collect::<Vec<_>>();
+        assert_eq!(t.iter().len(), forward.len());
+        assert_eq!(t.first().as_ref(), forward.first());
+        assert_eq!(t.last().as_ref(), forward.last());
+        forward.reverse();
+        assert_eq!(t.iter().rev().collect::<Vec<_>>(), forward);
+        let values = forward.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        assert_eq!(t.values().rev().collect::<Vec<_>>(), values);
+
+        // Taking from both ends meets in the middle without repeating entries
+        let mut iter = t.keys();
+        let mut seen = vec![];
+        while let Some(k) = iter.next() {
+            seen.push(k);
+            if let Some(k) = iter.next_back() {
+                seen.push(k);
+            }
+            assert_eq!(iter.len(), t.len() - seen.len());
+        }
+        seen.sort();
+        assert_eq!(seen, t.keys().collect::<Vec<_>>());
+
+        let hooks = t
+            .iter_prefix(&[".", ".git", "hooks"])
+            .rev()
+            .collect::<Vec<_>>();
+        assert_eq!(hooks.last().unwrap().0, [&".", &".git", &"hooks"]);
+        assert_eq!(hooks.len(), t.iter_prefix(&[".", ".git", "hooks"]).count());
+    }
+
+    fn reverse_backend<C>()
+    where
+        C: OrderedChildren<i32>,
+        C::Map<Trie<i32, i32, C>>: Default,
+    {
+        let mut t: Trie<i32, i32, C> = Trie::new(None);
+        assert_eq!(t.first(), None);
+        assert_eq!(t.last(), None);
+        for (k, v) in &iter_test_data() {
+            t.insert(k, *v);
+        }
+        let mut keys = t.keys().collect::<Vec<_>>();
+        keys.reverse();
+        assert_eq!(t.keys().rev().collect::<Vec<_>>(), keys);
+        assert_eq!(t.first(), Some((vec![&1], &1)));
+        assert_eq!(t.last(), Some((vec![&1, &3, &1, &1, &1], &13111)));
+    }
+
+    #[test]
+    fn reverse_backends() {
+        reverse_backend::<BTreeChildren>();
+        reverse_backend::<SortedVecChildren>();
+        reverse_backend::<InlineChildren<2>>();
+    }
+
+    fn range_backend<C>()
+    where
+        C: OrderedChildren<i32>,
+        C::Map<Trie<i32, i32, C>>: Default,
+    {
+        // Every sequence of up to three of 0, 1 and 2, apart from some to leave gaps
+        let mut seqs = vec![vec![]];
+        for len in 1..=3 {
+            let shorter = seqs.iter().filter(|s| s.len() == len - 1).cloned();
+            let longer = shorter
+                .flat_map(|s| (0..3).map(move |k| [&s[..], &[k]].concat()))
+                .collect::<Vec<_>>();
+            seqs.extend(longer);
+        }
+        let mut t: Trie<i32, i32, C> = Trie::new(None);
+        for (i, s) in seqs.iter().enumerate() {
+            if s.iter().sum::<i32>() % 3 != 1 {
+                t.insert(s, i as i32);
+            }
+        }
+        // Queries also reach past the stored keys
+        let outside = [vec![3], vec![1, 3], vec![-1]];
+        let mut bounds = vec![Bound::Unbounded];
+        for q in seqs.iter().chain(&outside) {
+            bounds.push(Bound::Included(&q[..]));
+            bounds.push(Bound::Excluded(&q[..]));
+        }
+        let within = |k: &[i32], start: Bound<&[i32]>, end: Bound<&[i32]>| {
+            (match start {
+                Bound::Included(s) => k >= s,
+                Bound::Excluded(s) => k > s,
+                Bound::Unbounded => true,
+            }) && (match end {
+                Bound::Included(e) => k <= e,
+                Bound::Excluded(e) => k < e,
+                Bound::Unbounded => true,
+            })
+        };
+        let all = t
+            .iter()
+            .map(|(k, v)| (k.into_iter().copied().collect::<Vec<_>>(), *v))
+            .collect::<Vec<_>>();
+        for &start in &bounds {
+            for &end in &bounds {
+                let expected = all
+                    .iter()
+                    .filter(|(k, _)| within(k, start, end))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let range = t.range((start, end));
+                assert_eq!(range.len(), expected.len());
+                let got = range
+                    .map(|(k, v)| (k.into_iter().copied().collect::<Vec<_>>(), *v))
+                    .collect::<Vec<_>>();
+                assert_eq!(got, expected, "{:?}..{:?}", start, end);
+                let mut rev = t
+                    .range((start, end))
+                    .rev()
+                    .map(|(_, v)| *v)
+                    .collect::<Vec<_>>();
+                rev.reverse();
+                assert!(rev.iter().eq(expected.iter().map(|(_, v)| v)));
+            }
+        }
+    }
+
+    #[test]
+    fn range() {
+        range_backend::<BTreeChildren>();
+        range_backend::<SortedVecChildren>();
+        range_backend::<InlineChildren<2>>();
+    }
+
+    #[test]
+    fn nearest_keys() {
+        let mut t: OrderedTrie<u32, u32> = OrderedTrie::new(None);
+        let stored: &[&[u32]] = &[&[1], &[1, 2], &[1, 2, 5], &[1, 4], &[3], &[3, 0, 0]];
+        for (i, keys) in stored.iter().enumerate() {
+            t.insert(*keys, i as u32);
+        }
+        let queries: &[&[u32]] = &[&[], &[0], &[1], &[1, 2, 5], &[1, 3], &[2], &[3, 0], &[4]];
+        for q in stored.iter().chain(queries) {
+            let q = *q;
+            let at_or_before = stored.iter().rposition(|k| *k <= q).map(|i| i as u32);
+            let before = stored.iter().rposition(|k| *k < q).map(|i| i as u32);
+            let at_or_after = stored.iter().position(|k| *k >= q).map(|i| i as u32);
+            let after = stored.iter().position(|k| *k > q).map(|i| i as u32);
+            assert_eq!(t.floor(q).map(|(_, v)| *v), at_or_before, "{:?}", q);
+            assert_eq!(t.predecessor(q).map(|(_, v)| *v), before, "{:?}", q);
+            assert_eq!(t.ceiling(q).map(|(_, v)| *v), at_or_after, "{:?}", q);
+            assert_eq!(t.successor(q).map(|(_, v)| *v), after, "{:?}", q);
+        }
+        assert_eq!(t.floor(&[3, 1]), Some((vec![&3, &0, &0], &5)));
+        assert_eq!(t.successor(&[3, 0, 0]), None);
+        assert_eq!(t.ceiling(vec![3u32, 0]), Some((vec![&3, &0, &0], &5)));
+
+        // Borrowed forms of the key elements, as with the other lookups
+        let mut t: Trie<String, u32, SortedVecChildren> = Trie::new(None);
+        t.insert(vec!["v1".to_string(), "4".to_string()], 14);
+        t.insert(vec!["v2".to_string()], 2);
+        assert_eq!(t.floor("v1/9".split('/')).map(|(_, v)| *v), Some(14));
+        assert_eq!(t.ceiling("v1/9".split('/')).map(|(_, v)| *v), Some(2));
+        let segs: &[&str] = &["v2"];
+        assert_eq!(
+            t.predecessor(segs.iter().copied()).map(|(_, v)| *v),
+            Some(14)
+        );
+        assert_eq!(t.successor(segs.iter().copied()), None);
+    }
+
+    #[test]
+    fn iter_prefix_testdata() {
+        let t = testdata_trie();
+        let hooks = t
+            .keys_with_prefix(&[".", ".git", "hooks"])
+            .map(|k| k.into_iter().copied().collect::<Vec<_>>().join("/"))
+            .collect::<Vec<_>>();
+        let mut expected = include_str!("../testdata.txt")
+            .lines()
+            .filter(|l| l.starts_with("./.git/hooks"))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(hooks, expected);
+    }
+
+    fn backend_ops<C>(sorted: bool)
+    where
+        C: Children<i32> + LookupChildren<i32, i32>,
+        C::Map<Trie<i32, i32, C>>: Default,
+    {
+        let mut t: Trie<i32, i32, C> = Trie::new(None);
+        // Out of order, so sorted backends have to shift entries around
+        for &k in &[5, 1, 4, 2, 3, 0, 6] {
+            t.insert(&[k], k);
+            t.insert(&[k, k], k * 11);
+        }
+        assert_eq!(t.replace(&[4], 40), Some(4));
+        assert_eq!(t.get(&[4]), Some(&40));
+        assert_eq!(t.get(&[4, 4]), Some(&44));
+        assert_eq!(t.get(&[4, 5]), None);
+        assert_eq!(t.get(&[7]), None);
+        *t.get_mut(&[6, 6]).unwrap() += 1;
+        assert_eq!(t.fetch(&[6, 6]), Some(67));
+        assert_eq!(t.remove(&[0, 0]), Some(0));
+        assert_eq!(t.remove(&[0]), Some(0));
+        assert_eq!(t.remove(&[3]), Some(3));
+        assert_eq!(t.remove(&[3, 3]), Some(33));
+        assert!(t.children.get(&3).is_none());
+        assert_eq!(t.iter().count(), 10);
+        if sorted {
+            let keys = t.keys().collect::<Vec<_>>();
+            let mut expected = keys.clone();
+            expected.sort();
+            assert_eq!(keys, expected);
+        }
+        // Mutable iteration visits the same entries in the same order
+        let expected = t
+            .iter()
+            .map(|(k, v)| (k.into_iter().cloned().collect::<Vec<_>>(), *v))
+            .collect::<Vec<_>>();
+        for v in t.values_mut() {
+            *v += 1000;
+        }
+        let bumped = t
+            .iter_mut()
+            .map(|(k, v)| (k.into_iter().cloned().collect::<Vec<_>>(), *v - 1000))
+            .collect::<Vec<_>>();
+        assert_eq!(bumped, expected);
+        for (_, v) in &mut t {
+            *v -= 1000;
+        }
+        let encoded: Vec<u8> = serde_cbor::to_vec(&t).unwrap();
+        let out: Trie<i32, i32, C> = serde_cbor::de::from_slice(&encoded).unwrap();
+        assert_eq!(out.fetch(&[5, 5]), Some(55));
+        let mut drained = t.drain_prefix(&[] as &[i32]).collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(drained.len(), 10);
+        assert_eq!(drained[0], (vec![1], 1));
+    }
+
+    #[test]
+    fn child_backends() {
+        backend_ops::<HashChildren>(false);
+        backend_ops::<HashChildren<BuildHasherDefault<DefaultHasher>>>(false);
+        backend_ops::<BTreeChildren>(true);
+        backend_ops::<SortedVecChildren>(true);
+        backend_ops::<InlineChildren>(true);
+        // Small enough that the root's children have to move to the heap
+        backend_ops::<InlineChildren<2>>(true);
+        backend_ops::<InlineChildren<0>>(true);
+    }
+
+    #[derive(Clone)]
+    struct SeededState(u64);
+
+    impl BuildHasher for SeededState {
+        type Hasher = DefaultHasher;
+
+        fn build_hasher(&self) -> DefaultHasher {
+            let mut h = DefaultHasher::new();
+            h.write_u64(self.0);
+            h
+        }
+    }
+
+    #[test]
+    fn custom_hasher() {
+        let data = iter_test_data();
+        let mut t: Trie<i32, i32, HashChildren<_>> = Trie::with_hasher(None, SeededState(7));
+        t.extend(data.iter().map(|(k, v)| (k, *v)));
+        assert_eq!(t.hasher().0, 7);
+        assert_eq!(t.children.get(&1).unwrap().hasher().0, 7);
+        assert_eq!(t.get(&[1, 2, 1]), Some(&121));
+
+        // The same seed lays siblings out the same way every time
+        let mut u: Trie<i32, i32, HashChildren<_>> = Trie::with_hasher(None, SeededState(7));
+        u.extend(data.iter().map(|(k, v)| (k, *v)));
+        assert_eq!(t.iter().collect::<Vec<_>>(), u.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn serialize_ordered() {
+        let t: OrderedTrie<i32, i32> = iter_test_data().iter().map(|(k, v)| (k, *v)).collect();
+        let encoded: Vec<u8> = serde_cbor::to_vec(&t).unwrap();
+        let out: OrderedTrie<i32, i32> = serde_cbor::de::from_slice(&encoded).unwrap();
+        assert_eq!(out.iter().collect::<Vec<_>>(), t.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    /// assert that serde can tell the difference between None and Some(()), now that each node's
+    /// value is serialized as a sequence of zero or one elements rather than as an Option
     fn serialize_none_vs_unit() {
         let mut t: Trie<&str, ()> = Trie::new(None);
         t.insert(&["yes_exist"], ());
         let encoded: Vec<u8> = serde_cbor::to_vec(&t).unwrap();
         let out: Trie<&str, ()> = serde_cbor::de::from_slice(&encoded).unwrap();
-        assert_eq!(out.fetch(&["yes_exist"]), out.fetch(&["no_exist"]));
+        assert_eq!(out.fetch(&["yes_exist"]), Some(()));
+        assert_eq!(out.fetch(&["no_exist"]), None);
+    }
+
+    #[test]
+    fn serialized_size_is_linear() {
+        // A chain with a value at every depth, where writing out each value's whole key sequence
+        // would take space quadratic in the depth
+        let mut sizes = vec![];
+        for &depth in &[250, 1000] {
+            let keys = vec![7u8; depth];
+            let mut t: Trie<u8, u8> = Trie::new(None);
+            for d in 1..=depth {
+                t.insert(&keys[..d], 1);
+            }
+            let encoded = serde_cbor::to_vec(&t).unwrap();
+            // A node takes 5 bytes: the record, the levels to pop, the key and the value in a
+            // sequence of its own
+            assert!(encoded.len() <= 5 * depth + 8, "{} nodes", depth);
+            let out: Trie<u8, u8> = serde_cbor::de::from_slice(&encoded).unwrap();
+            assert_eq!(out.len(), depth);
+            assert_eq!(out.get(&keys), Some(&1));
+            sizes.push(encoded.len());
+        }
+        // Four times the nodes take at most four times the space, plus the fixed overhead
+        assert!(sizes[1] <= 4 * sizes[0] + 8, "{:?}", sizes);
+    }
+
+    #[test]
+    fn deserialize_malformed() {
+        // Going up from the first node below the root would leave the trie
+        let encoded = serde_cbor::to_vec(&([] as [u8; 0], (0, 1, [1]), (2, 2, [2]))).unwrap();
+        assert!(serde_cbor::de::from_slice::<Trie<u8, u8>>(&encoded).is_err());
+        // Two children with the same key
+        let encoded = serde_cbor::to_vec(&([] as [u8; 0], (0, 1, [1]), (1, 1, [2]))).unwrap();
+        assert!(serde_cbor::de::from_slice::<Trie<u8, u8>>(&encoded).is_err());
+        // Two values at one node
+        let encoded = serde_cbor::to_vec(&([] as [u8; 0], (0, 1, [1, 2]))).unwrap();
+        assert!(serde_cbor::de::from_slice::<Trie<u8, u8>>(&encoded).is_err());
+        let encoded = serde_cbor::to_vec(&([] as [u8; 0], (0, 1, [1]), (1, 2, [2]))).unwrap();
+        let t: Trie<u8, u8> = serde_cbor::de::from_slice(&encoded).unwrap();
+        assert_eq!(t.get(&[2]), Some(&2));
     }
 
     #[test]